@@ -0,0 +1,137 @@
+/*
+ * C API of the Tsunami chess engine, implemented in src/ffi.rs
+ *
+ * Link against the tsunami shared library built by `cargo build --release`.
+ * Functions returning int return 0 on success and -1 on error, with the
+ * message available from tsunami_last_error(). An engine must only be used
+ * by one thread at a time. Different engines can be used by different
+ * threads, each has its own position, network and options. Only the search
+ * parameters of builds with the tune feature are shared by every engine.
+ */
+
+#ifndef TSUNAMI_H
+#define TSUNAMI_H
+
+/* Generated by cbindgen from src/ffi.rs, run `UPDATE_HEADER=1 cargo test --test ffi` after changing it */
+
+#include <stdint.h>
+
+/**
+ * Long enough for a UCI move such as `e7e8q` and the terminating zero
+ */
+#define TSUNAMI_MOVE_LEN 8
+
+typedef struct TsunamiEngine TsunamiEngine;
+
+/**
+ * Called after every completed iteration with its principal variation, as
+ * UCI moves separated by spaces. The string only lives for the call.
+ */
+typedef void (*TsunamiInfoCallback)(int depth,
+                                    int eval,
+                                    uint64_t nodes,
+                                    const char *pv,
+                                    void *user_data);
+
+typedef struct {
+  /**
+   * In UCI notation, empty when there is no legal move
+   */
+  char best_move[TSUNAMI_MOVE_LEN];
+  /**
+   * Empty when the search has no reply to the best move
+   */
+  char ponder_move[TSUNAMI_MOVE_LEN];
+  /**
+   * In centipawns for the side to move
+   */
+  int eval;
+  int depth;
+  uint64_t nodes;
+  uint64_t time_ms;
+} TsunamiSearchResult;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * A new engine at the starting position, to be freed with `tsunami_free`
+ */
+TsunamiEngine *tsunami_new(void);
+
+/**
+ * # Safety
+ * `engine` is null or comes from `tsunami_new` and hasn't been freed
+ */
+void tsunami_free(TsunamiEngine *engine);
+
+/**
+ * The message of the last error on this thread, or null. It lives until the
+ * next error on the thread.
+ */
+const char *tsunami_last_error(void);
+
+/**
+ * Sets up an X-FEN or Shredder-FEN, the position is unchanged on error
+ *
+ * # Safety
+ * `engine` comes from `tsunami_new`, `fen` is a zero-terminated string
+ */
+int tsunami_set_fen(TsunamiEngine *engine, const char *fen);
+
+/**
+ * Plays UCI moves separated by spaces, none of them if one is invalid
+ *
+ * # Safety
+ * `engine` comes from `tsunami_new`, `moves` is a zero-terminated string
+ */
+int tsunami_play_moves(TsunamiEngine *engine, const char *moves);
+
+/**
+ * Sets `EvalFile`, `Evaluation` or `UCI_Chess960` of this engine only
+ *
+ * # Safety
+ * `engine` comes from `tsunami_new`, `name` and `value` are zero-terminated
+ * strings
+ */
+int tsunami_set_option(TsunamiEngine *engine, const char *name, const char *value);
+
+/**
+ * Searches the current position until one of the limits is reached, a limit
+ * of 0 is no limit. `callback` may be null.
+ *
+ * # Safety
+ * `engine` comes from `tsunami_new` and `result` points to a writable
+ * `TsunamiSearchResult`
+ */
+int tsunami_search(TsunamiEngine *engine,
+                   int depth,
+                   uint64_t nodes,
+                   uint64_t time_ms,
+                   TsunamiInfoCallback callback,
+                   void *user_data,
+                   TsunamiSearchResult *result);
+
+/**
+ * The static evaluation of the current position in centipawns, for the side
+ * to move
+ *
+ * # Safety
+ * `engine` comes from `tsunami_new`
+ */
+int tsunami_eval(const TsunamiEngine *engine);
+
+/**
+ * The number of leaf nodes of the current position at `depth`
+ *
+ * # Safety
+ * `engine` comes from `tsunami_new`
+ */
+uint64_t tsunami_perft(const TsunamiEngine *engine, int depth);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* TSUNAMI_H */
@@ -0,0 +1,234 @@
+//! Polyglot opening books
+//!
+//! A book is a list of 16 byte big-endian entries sorted by key: the Polyglot
+//! key of the position, the move, its weight and 4 bytes of learning data
+//! which are ignored.
+
+pub mod builder;
+pub mod keys;
+
+use std::{fmt, fs, io, path::Path};
+
+use cozy_chess::{Board, File, Move, Piece, Rank, Square};
+
+use crate::rng::Rng;
+
+pub const ENTRY_SIZE: usize = 16;
+
+/// How a move is picked from the book moves of a position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    /// Randomly, in proportion to the weights
+    Weighted,
+    /// The move with the highest weight
+    Best,
+}
+
+impl Selection {
+    pub fn name(self) -> &'static str {
+        match self {
+            Selection::Weighted => "Weighted",
+            Selection::Best => "Best",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl Entry {
+    fn from_bytes(bytes: &[u8; ENTRY_SIZE]) -> Self {
+        Self {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes([bytes[8], bytes[9]]),
+            weight: u16::from_be_bytes([bytes[10], bytes[11]]),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mv.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+#[derive(Debug)]
+pub enum BookError {
+    Io(io::Error),
+    /// The file size is not a multiple of the entry size
+    Size(usize),
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::Io(err) => write!(f, "{}", err),
+            BookError::Size(size) => write!(
+                f,
+                "book size {} is not a multiple of {} bytes",
+                size, ENTRY_SIZE
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for BookError {
+    fn from(err: io::Error) -> Self {
+        BookError::Io(err)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Book {
+    entries: Vec<Entry>,
+}
+
+impl Book {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, BookError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookError> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(BookError::Size(bytes.len()));
+        }
+        let mut entries: Vec<Entry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| Entry::from_bytes(entry.try_into().unwrap()))
+            .collect();
+        // Books are meant to be sorted, but lookups rely on it
+        entries.sort_by_key(|entry| entry.key);
+        Ok(Self { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The legal book moves of the position with their weights
+    pub fn moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        let key = keys::hash(board);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| {
+                let mv = decode_move(entry.mv)?;
+                board.is_legal(mv).then_some((mv, entry.weight))
+            })
+            .collect()
+    }
+
+    /// Picks a book move for the position
+    pub fn probe(&self, board: &Board, selection: Selection, rng: &mut Rng) -> Option<Move> {
+        let moves = self.moves(board);
+        match selection {
+            Selection::Best => moves
+                .iter()
+                .max_by_key(|(_, weight)| *weight)
+                .map(|&(mv, _)| mv),
+            Selection::Weighted => {
+                let total: u64 = moves.iter().map(|&(_, weight)| u64::from(weight)).sum();
+                if total == 0 {
+                    return None;
+                }
+                let mut pick = rng.below(total);
+                moves.into_iter().find_map(|(mv, weight)| {
+                    if pick < u64::from(weight) {
+                        Some(mv)
+                    } else {
+                        pick -= u64::from(weight);
+                        None
+                    }
+                })
+            }
+        }
+    }
+}
+
+const PROMOTIONS: [Option<Piece>; 5] = [
+    None,
+    Some(Piece::Knight),
+    Some(Piece::Bishop),
+    Some(Piece::Rook),
+    Some(Piece::Queen),
+];
+
+/// Polyglot moves are `to` file and rank, `from` file and rank and the
+/// promotion piece, 3 bits each. Castling is king takes rook, like cozy-chess.
+pub fn decode_move(mv: u16) -> Option<Move> {
+    let square = |bits: u16| {
+        Square::new(
+            File::index(usize::from(bits & 7)),
+            Rank::index(usize::from(bits >> 3 & 7)),
+        )
+    };
+    Some(Move {
+        from: square(mv >> 6),
+        to: square(mv),
+        promotion: *PROMOTIONS.get(usize::from(mv >> 12 & 7))?,
+    })
+}
+
+pub fn encode_move(mv: Move) -> u16 {
+    let promotion = PROMOTIONS
+        .iter()
+        .position(|&pc| pc == mv.promotion)
+        .unwrap_or(0) as u16;
+    promotion << 12 | (mv.from as u16) << 6 | mv.to as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_book_probe() {
+        let board = Board::default();
+        let key = keys::hash(&board);
+        let entry = |mv: &str, weight| Entry {
+            key,
+            mv: encode_move(mv.parse().unwrap()),
+            weight,
+            learn: 0,
+        };
+        // An illegal move, and a move of another position, are never picked
+        let entries = [
+            entry("e2e4", 30),
+            entry("d2d4", 10),
+            entry("e2e5", 100),
+            Entry {
+                key: key + 1,
+                ..entry("g1f3", 500)
+            },
+        ];
+        let bytes: Vec<u8> = entries.iter().flat_map(|e| e.to_bytes()).collect();
+        let book = Book::from_bytes(&bytes).unwrap();
+
+        let mut rng = Rng::new(1);
+        assert_eq!(
+            book.probe(&board, Selection::Best, &mut rng),
+            Some("e2e4".parse().unwrap())
+        );
+        for _ in 0..20 {
+            let mv = book.probe(&board, Selection::Weighted, &mut rng).unwrap();
+            assert!(["e2e4", "d2d4"].contains(&mv.to_string().as_str()));
+        }
+
+        let castle: Move = "e1h1".parse().unwrap();
+        assert_eq!(decode_move(encode_move(castle)), Some(castle));
+    }
+}
@@ -0,0 +1,181 @@
+//! Builds Polyglot books from PGN games
+//!
+//! Every game is replayed up to `max_ply` and each move counts towards the
+//! statistics of the position it was played in. A move is kept if it was played
+//! at least `min_count` times and scored at least `min_score` percent for the
+//! side that played it. Its weight is its score in half points (two for a win,
+//! one for a draw), so better moves are picked more often. Moves that only lost
+//! have no weight, weighted selection never picks them.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+};
+
+use cozy_chess::Color;
+
+use super::{encode_move, keys, Entry};
+use crate::pgn::{self, Game};
+
+#[derive(Clone, Debug)]
+pub struct BuilderOptions {
+    pub pgns: Vec<String>,
+    pub out: String,
+    pub max_ply: usize,
+    pub min_count: u32,
+    /// In percent, for the side playing the move
+    pub min_score: f32,
+}
+
+impl Default for BuilderOptions {
+    fn default() -> Self {
+        Self {
+            pgns: Vec::new(),
+            out: String::from("book.bin"),
+            max_ply: 24,
+            min_count: 1,
+            min_score: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct MoveStats {
+    count: u32,
+    /// Two for a win, one for a draw
+    points: u32,
+}
+
+/// Statistics of the moves played in every position, by Polyglot key
+#[derive(Debug, Default)]
+pub struct BookBuilder {
+    stats: HashMap<(u64, u16), MoveStats>,
+    games: u64,
+}
+
+impl BookBuilder {
+    /// Adds the main line of a finished game
+    pub fn add_game(&mut self, game: &Game, max_ply: usize) {
+        let Some(result) = game.white_score() else {
+            return;
+        };
+        let mut board = game.start.clone();
+        for mv in game.mainline().take(max_ply) {
+            let points = match board.side_to_move() {
+                Color::White => (2.0 * result) as u32,
+                Color::Black => (2.0 * (1.0 - result)) as u32,
+            };
+            let stats = self
+                .stats
+                .entry((keys::hash(&board), encode_move(mv)))
+                .or_default();
+            stats.count += 1;
+            stats.points += points;
+            board.play_unchecked(mv);
+        }
+        self.games += 1;
+    }
+
+    /// The book entries passing the filters, sorted by key
+    pub fn entries(&self, min_count: u32, min_score: f32) -> Vec<Entry> {
+        let mut entries: Vec<Entry> = self
+            .stats
+            .iter()
+            .filter(|(_, stats)| {
+                let score = 100.0 * stats.points as f32 / (2 * stats.count) as f32;
+                stats.count >= min_count && score >= min_score
+            })
+            .map(|(&(key, mv), stats)| Entry {
+                key,
+                mv,
+                weight: stats.points.min(u32::from(u16::MAX)) as u16,
+                learn: 0,
+            })
+            .collect();
+        entries.sort_by_key(|entry| (entry.key, u16::MAX - entry.weight, entry.mv));
+        entries
+    }
+}
+
+/// Reads the PGN files and writes the book
+pub fn build(options: &BuilderOptions) -> io::Result<()> {
+    let mut builder = BookBuilder::default();
+    let mut unreadable = 0;
+    for path in &options.pgns {
+        let pgn = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+        for game in pgn::Reader::new(&pgn) {
+            match game {
+                Ok(game) => builder.add_game(&game, options.max_ply),
+                Err(_) => unreadable += 1,
+            }
+        }
+    }
+    if unreadable > 0 {
+        println!("Skipped {} games that couldn't be read", unreadable);
+    }
+
+    let entries = builder.entries(options.min_count, options.min_score);
+    let mut out = BufWriter::new(File::create(&options.out)?);
+    for entry in &entries {
+        out.write_all(&entry.to_bytes())?;
+    }
+    out.flush()?;
+
+    println!(
+        "Wrote {} entries from {} games to {}",
+        entries.len(),
+        builder.games,
+        options.out
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use cozy_chess::Board;
+
+    use super::*;
+
+    #[test]
+    fn test_build_from_pgn() {
+        let pgn = r#"
+[Event "Test"]
+[Result "1-0"]
+
+1. e4 {best by test} e5 2. Nf3 (2. f4 exf4) Nc6 3. Bb5 a6 $1 4. O-O 1-0
+
+[Event "Test"]
+[Result "1/2-1/2"]
+
+1. e4 c5 2. Nf3 d6 1/2-1/2
+
+[Event "Test"]
+[Result "0-1"]
+
+1. d4 d5 0-1
+"#;
+        let mut builder = BookBuilder::default();
+        for game in pgn::parse(pgn).unwrap() {
+            builder.add_game(&game, 2);
+        }
+        let start = keys::hash(&Board::default());
+        let first_moves = |min_score: f32| -> Vec<(u16, u16)> {
+            builder
+                .entries(1, min_score)
+                .iter()
+                .filter(|entry| entry.key == start)
+                .map(|entry| (entry.mv, entry.weight))
+                .collect()
+        };
+        // 1. e4 scored a win and a draw, 1. d4 lost and is only left out by
+        // a minimum score
+        let e4 = (encode_move("e2e4".parse().unwrap()), 3);
+        let d4 = (encode_move("d2d4".parse().unwrap()), 0);
+        assert_eq!(first_moves(0.0), [e4, d4]);
+        assert_eq!(first_moves(1.0), [e4]);
+
+        // 1... e5 and 1... c5 were played once each, leaving only 1. e4
+        assert_eq!(builder.entries(2, 0.0).len(), 1);
+    }
+}
@@ -0,0 +1,138 @@
+//! Chess960 castling in FENs and UCI moves
+//!
+//! cozy-chess stores castling rights as rook files and castling moves as the
+//! king capturing its own rook. UCI writes castling as the king moving two
+//! squares, or as king takes rook with `UCI_Chess960`.
+
+use cozy_chess::{Board, Color, FenParseError, File, Move, Piece, Rank, Square};
+
+/// Parses a FEN with castling rights in X-FEN or Shredder-FEN
+/// In X-FEN, `K` and `Q` are the outermost rooks on each side of the king and
+/// inner rooks are given by their file, like in Shredder-FEN.
+pub fn parse_fen(fen: &str) -> Result<Board, FenParseError> {
+    let mut fields: Vec<&str> = fen.split_whitespace().collect();
+    let Some(&castling) = fields.get(2) else {
+        return Board::from_fen(fen, true);
+    };
+    let placement = {
+        let mut fields = fields.clone();
+        fields[2] = "-";
+        Board::from_fen(&fields.join(" "), true)?
+    };
+
+    let mut shredder = String::new();
+    for c in castling.chars() {
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let king = placement.king(color).file();
+        let rooks = placement.colored_pieces(color, Piece::Rook)
+            & Rank::First.relative_to(color).bitboard();
+        let right = match c.to_ascii_lowercase() {
+            'k' => rooks
+                .into_iter()
+                .map(Square::file)
+                .filter(|&f| f > king)
+                .max(),
+            'q' => rooks
+                .into_iter()
+                .map(Square::file)
+                .filter(|&f| f < king)
+                .min(),
+            _ => None,
+        }
+        .map_or(c, char::from);
+        shredder.push(match color {
+            Color::White => right.to_ascii_uppercase(),
+            Color::Black => right,
+        });
+    }
+    fields[2] = &shredder;
+    Board::from_fen(&fields.join(" "), true)
+}
+
+/// The FEN of a board, with castling rights in Shredder-FEN for Chess960
+pub fn to_fen(board: &Board, chess960: bool) -> String {
+    if chess960 {
+        format!("{:#}", board)
+    } else {
+        board.to_string()
+    }
+}
+
+/// Converts a move from cozy-chess to UCI
+pub fn to_uci(board: &Board, mut mv: Move, chess960: bool) -> Move {
+    if !chess960 && board.color_on(mv.to) == board.color_on(mv.from) {
+        let file = if mv.to.file() > mv.from.file() {
+            File::G
+        } else {
+            File::C
+        };
+        mv.to = Square::new(file, mv.to.rank());
+    }
+    mv
+}
+
+/// Converts a move from UCI to cozy-chess
+pub fn from_uci(board: &Board, mut mv: Move, chess960: bool) -> Move {
+    if chess960 || board.piece_on(mv.from) != Some(Piece::King) || board.is_legal(mv) {
+        return mv;
+    }
+    let rights = board.castle_rights(board.side_to_move());
+    let rook = match mv.to.file() {
+        File::G => rights.short,
+        File::C => rights.long,
+        _ => None,
+    };
+    if let Some(file) = rook {
+        mv.to = Square::new(file, mv.from.rank());
+    }
+    mv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{perft::SUITE, position::legal_moves};
+
+    #[test]
+    fn test_castling_notation() {
+        let xfen =
+            parse_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9").unwrap();
+        let shredder = Board::from_fen(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            true,
+        )
+        .unwrap();
+        assert_eq!(xfen, shredder);
+        assert_eq!(
+            to_fen(&xfen, true),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"
+        );
+
+        // An inner rook is given by its file in X-FEN
+        let board = parse_fen("4k3/8/8/8/8/8/8/RR2K3 w B - 0 1").unwrap();
+        assert_eq!(board.castle_rights(Color::White).long, Some(File::B));
+
+        // Standard notation is only unambiguous in standard positions
+        for (fen, _) in SUITE {
+            let board = parse_fen(fen).unwrap();
+            let standard = Board::from_fen(fen, false).is_ok_and(|b| b == board);
+            for chess960 in [true, false] {
+                if !chess960 && !standard {
+                    continue;
+                }
+                for mv in legal_moves(&board) {
+                    let uci = to_uci(&board, mv, chess960);
+                    assert_eq!(from_uci(&board, uci, chess960), mv, "{} {}", fen, uci);
+                }
+            }
+        }
+        let board = Board::default();
+        let castle: Move = "e1h1".parse().unwrap();
+        assert_eq!(to_uci(&board, castle, false).to_string(), "e1g1");
+        assert_eq!(to_uci(&board, castle, true), castle);
+    }
+}
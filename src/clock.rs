@@ -0,0 +1,56 @@
+//! Time for the search and for seeding random numbers
+//!
+//! `std::time::Instant` panics on `wasm32-unknown-unknown`, where there is no
+//! clock without the host. There the host provides the time through the
+//! imported `tsunami_now_ms`, see `web/tsunami.mjs`.
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub use std::time::Instant;
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub use wasm::Instant;
+
+/// Nanoseconds from a point that changes between runs
+pub fn time_seed() -> u64 {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64)
+    }
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    {
+        (wasm::now_ms() * 1e6) as u64
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+mod wasm {
+    use std::time::Duration;
+
+    #[link(wasm_import_module = "env")]
+    extern "C" {
+        /// Milliseconds since the epoch, such as `Date.now()` in JavaScript
+        fn tsunami_now_ms() -> f64;
+    }
+
+    pub fn now_ms() -> f64 {
+        // Safe, the import takes nothing and returns a number
+        unsafe { tsunami_now_ms() }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+    pub struct Instant(f64);
+
+    impl Instant {
+        pub fn now() -> Self {
+            Self(now_ms())
+        }
+
+        pub fn elapsed(&self) -> Duration {
+            Duration::from_secs_f64((now_ms() - self.0).max(0.0) / 1000.0)
+        }
+    }
+}
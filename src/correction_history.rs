@@ -0,0 +1,94 @@
+use cozy_chess::{Color, Piece};
+
+use crate::{position::Position, tunable};
+
+/// Number of pawn structure entries per side to move
+const SIZE: usize = 16384;
+/// Corrections are stored with extra precision
+const GRAIN: i32 = 256;
+/// The weight of an update is out of this value
+const WEIGHT_SCALE: i32 = 256;
+/// Largest correction that can be stored, in grains
+const MAX: i32 = 64 * GRAIN;
+
+/// Learns the difference between the static evaluation and the search result
+/// for each pawn structure, so the static evaluation can be corrected
+#[derive(Clone, Debug)]
+pub struct CorrectionHistory {
+    table: Vec<[i32; 2]>,
+}
+
+impl CorrectionHistory {
+    /// Corrects a raw static evaluation of the position
+    pub fn correct(&self, pos: &Position, eval: i32) -> i32 {
+        let entry = self.table[pawn_index(pos)][pos.board.side_to_move() as usize];
+        eval + entry / GRAIN
+    }
+
+    /// Moves the correction of this pawn structure towards the difference
+    /// between the search score and the raw static evaluation
+    pub fn update(&mut self, pos: &Position, depth: u8, score: i32, eval: i32) {
+        let entry = &mut self.table[pawn_index(pos)][pos.board.side_to_move() as usize];
+        let diff = (score - eval) * GRAIN;
+        // Deeper searches are more trustworthy
+        let weight = (depth as i32 + 1).min(tunable::corr_hist_max_weight());
+
+        *entry =
+            ((*entry * (WEIGHT_SCALE - weight) + diff * weight) / WEIGHT_SCALE).clamp(-MAX, MAX);
+    }
+}
+
+impl Default for CorrectionHistory {
+    fn default() -> Self {
+        Self {
+            table: vec![[0; 2]; SIZE],
+        }
+    }
+}
+
+/// Hashes the pawn structure of the position into a table index
+fn pawn_index(pos: &Position) -> usize {
+    let pawns = pos.board.pieces(Piece::Pawn);
+    let white = (pawns & pos.board.colors(Color::White)).0;
+    let black = (pawns & pos.board.colors(Color::Black)).0;
+
+    let hash =
+        white.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ black.wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    (hash >> 50) as usize % SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let pos = Position::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        let mut corr_hist = CorrectionHistory::default();
+        assert_eq!(corr_hist.correct(&pos, 50), 50);
+
+        // Every update moves the corrected evaluation towards the search score
+        let mut corrected = 50;
+        for _ in 0..200 {
+            corr_hist.update(&pos, 10, 90, 50);
+            let next = corr_hist.correct(&pos, 50);
+            assert!(next >= corrected && next <= 90);
+            corrected = next;
+        }
+        assert!(corrected > 80);
+
+        // The other side to move and other pawn structures are unaffected
+        let white =
+            Position::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(corr_hist.correct(&white, 50), 50);
+        let other =
+            Position::from_fen("rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq - 0 1");
+        assert_eq!(corr_hist.correct(&other, 50), 50);
+
+        // Huge differences are clamped
+        for _ in 0..200 {
+            corr_hist.update(&pos, 10, -5000, 50);
+        }
+        assert_eq!(corr_hist.correct(&pos, 50), 50 - MAX / GRAIN);
+    }
+}
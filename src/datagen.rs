@@ -0,0 +1,339 @@
+//! Self-play training data generation
+//!
+//! Games start from a few random moves and are then played out with fixed-node
+//! searches. Quiet positions are written with the search score and the result
+//! of the game, either in the 32 byte `ChessBoard` layout of bulletformat
+//! (used by bullet and most other NNUE trainers), or as text lines of
+//! `<fen> | <score> | <result>` from white's point of view.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use cozy_chess::{Board, BoardBuilder, Color, GameStatus, Move, Piece, Square};
+
+use crate::{
+    position::{legal_moves, Position},
+    rng::Rng,
+    search::{is_mate_score, report::SilentReporter, search},
+};
+
+const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+/// Size of a position in the binary format
+pub const ENTRY_SIZE: usize = 32;
+/// Openings the search already thinks are decided are thrown away
+const MAX_OPENING_EVAL: i32 = 1000;
+/// Games that go on for longer are adjudicated as draws
+const MAX_GAME_PLIES: usize = 400;
+const SEARCH_DEPTH: u8 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataFormat {
+    /// bulletformat `ChessBoard`s
+    Binary,
+    /// `<fen> | <score> | <result>` lines
+    Text,
+}
+
+#[derive(Clone, Debug)]
+pub struct DatagenOptions {
+    pub threads: usize,
+    pub games: u64,
+    /// Node limit of every search, searches stop as soon as they reach it
+    pub nodes: u64,
+    /// Number of random moves at the start of each game
+    pub random_plies: usize,
+    pub format: DataFormat,
+    pub out: String,
+}
+
+impl Default for DatagenOptions {
+    fn default() -> Self {
+        Self {
+            threads: 1,
+            games: 100,
+            nodes: 5000,
+            random_plies: 8,
+            format: DataFormat::Binary,
+            out: String::from("data.bin"),
+        }
+    }
+}
+
+/// A position worth training on, with the score from the side to move
+struct Sample {
+    board: Board,
+    score: i32,
+}
+
+/// Plays `options.games` games across `options.threads` threads
+pub fn run(options: &DatagenOptions) -> io::Result<()> {
+    let out = Mutex::new(BufWriter::new(File::create(&options.out)?));
+    let games_started = AtomicU64::new(0);
+    let games_done = AtomicU64::new(0);
+    let positions = AtomicU64::new(0);
+    let start_time = Instant::now();
+
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..options.threads.max(1))
+            .map(|id| {
+                let (out, games_started, games_done, positions) =
+                    (&out, &games_started, &games_done, &positions);
+                s.spawn(move || -> io::Result<()> {
+                    let mut rng = Rng::from_time(id as u64);
+                    let mut buf = Vec::new();
+
+                    while games_started.fetch_add(1, Ordering::Relaxed) < options.games {
+                        let (samples, result) = loop {
+                            if let Some(game) = play_game(&mut rng, options) {
+                                break game;
+                            }
+                        };
+
+                        buf.clear();
+                        for sample in &samples {
+                            write_sample(&mut buf, sample, result, options.format);
+                        }
+                        out.lock().unwrap().write_all(&buf)?;
+
+                        let total = positions.fetch_add(samples.len() as u64, Ordering::Relaxed)
+                            + samples.len() as u64;
+                        let done = games_done.fetch_add(1, Ordering::Relaxed) + 1;
+                        if done % 100 == 0 || done == options.games {
+                            let elapsed = start_time.elapsed().as_secs_f64();
+                            println!(
+                                "games {} positions {} positions/s {:.0}",
+                                done,
+                                total,
+                                total as f64 / elapsed
+                            );
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().unwrap())
+    })?;
+
+    out.into_inner().unwrap().flush()
+}
+
+/// Plays a game, returning the positions worth training on and the result
+/// for white (1 for a win, 0.5 for a draw, 0 for a loss)
+/// Returns `None` if the random opening was unusable
+fn play_game(rng: &mut Rng, options: &DatagenOptions) -> Option<(Vec<Sample>, f32)> {
+    let mut pos = Position::from_fen(STARTPOS);
+
+    for _ in 0..options.random_plies {
+        let moves = legal_moves(&pos.board);
+        if moves.is_empty() {
+            return None;
+        }
+        pos.make_move(moves[rng.below(moves.len() as u64) as usize]);
+    }
+    if pos.board.status() != GameStatus::Ongoing {
+        return None;
+    }
+    let opening = search(
+        &mut pos,
+        SEARCH_DEPTH,
+        u128::MAX,
+        options.nodes,
+        &mut SilentReporter,
+    );
+    if opening.eval.abs() > MAX_OPENING_EVAL {
+        return None;
+    }
+
+    let mut samples = Vec::new();
+    let mut plies = 0;
+    let result = loop {
+        match pos.board.status() {
+            // The side to move has been checkmated
+            GameStatus::Won => {
+                break match pos.board.side_to_move() {
+                    Color::White => 0.0,
+                    Color::Black => 1.0,
+                };
+            }
+            GameStatus::Drawn => break 0.5,
+            GameStatus::Ongoing => {}
+        }
+        if is_threefold(&pos) || is_insufficient_material(&pos.board) {
+            break 0.5;
+        }
+        if plies >= MAX_GAME_PLIES {
+            break 0.5;
+        }
+        plies += 1;
+
+        let search_info = search(
+            &mut pos,
+            SEARCH_DEPTH,
+            u128::MAX,
+            options.nodes,
+            &mut SilentReporter,
+        );
+        let mv = search_info.best_move.unwrap();
+
+        // Positions in check, where the best move is a capture or where a mate
+        // has been found are too noisy for a static evaluation to learn from
+        let in_check = !pos.board.checkers().is_empty();
+        if !in_check && !is_capture(&pos.board, mv) && !is_mate_score(search_info.eval) {
+            samples.push(Sample {
+                board: pos.board.clone(),
+                score: search_info.eval,
+            });
+        }
+
+        pos.make_move(mv);
+    };
+
+    Some((samples, result))
+}
+
+fn is_capture(board: &Board, mv: Move) -> bool {
+    let en_passant = board.piece_on(mv.from) == Some(Piece::Pawn) && mv.from.file() != mv.to.file();
+    board.color_on(mv.to) == Some(!board.side_to_move()) || en_passant
+}
+
+/// The current position has been reached three times
+fn is_threefold(pos: &Position) -> bool {
+    let hash = pos.board.hash();
+    pos.repeition_history.iter().filter(|&&h| h == hash).count() >= 3
+}
+
+/// Neither side can checkmate: bare kings, or a single minor piece
+fn is_insufficient_material(board: &Board) -> bool {
+    let minors = board.pieces(Piece::Knight) | board.pieces(Piece::Bishop);
+    board.occupied().len() == 2 || (board.occupied().len() == 3 && minors.len() == 1)
+}
+
+fn write_sample(buf: &mut Vec<u8>, sample: &Sample, white_result: f32, format: DataFormat) {
+    let stm = sample.board.side_to_move();
+    match format {
+        DataFormat::Binary => {
+            let result = match stm {
+                Color::White => white_result,
+                Color::Black => 1.0 - white_result,
+            };
+            let score = sample.score.clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+            buf.extend_from_slice(&to_chess_board(&sample.board, score, result));
+        }
+        DataFormat::Text => {
+            let score = match stm {
+                Color::White => sample.score,
+                Color::Black => -sample.score,
+            };
+            buf.extend_from_slice(
+                format!("{} | {} | {:.1}\n", sample.board, score, white_result).as_bytes(),
+            );
+        }
+    }
+}
+
+/// Encodes a position as a bulletformat `ChessBoard`:
+/// the occupancy, 4 bits per occupied square (colour bit and piece), the score,
+/// the result (0, 1 or 2 for a loss, draw or win) and both king squares.
+/// Everything is from the side to move's point of view, so positions with black
+/// to move are flipped vertically.
+pub fn to_chess_board(board: &Board, score: i16, result: f32) -> [u8; ENTRY_SIZE] {
+    let stm = board.side_to_move();
+    let flip = |bb: u64| match stm {
+        Color::White => bb,
+        Color::Black => bb.swap_bytes(),
+    };
+    let ours = flip(board.colors(stm).0);
+    let theirs = flip(board.colors(!stm).0);
+    let pieces = Piece::ALL.map(|pc| flip(board.pieces(pc).0));
+    let occ = ours | theirs;
+
+    let mut pcs = [0u8; 16];
+    let mut bits = occ;
+    let mut i = 0;
+    while bits != 0 {
+        let sq_bit = bits & bits.wrapping_neg();
+        let colour = u8::from(theirs & sq_bit != 0) << 3;
+        let pc = pieces.iter().position(|&bb| bb & sq_bit != 0).unwrap() as u8;
+        pcs[i / 2] |= (colour | pc) << (4 * (i & 1));
+        i += 1;
+        bits &= bits - 1;
+    }
+
+    let kings = pieces[Piece::King as usize];
+    let mut entry = [0u8; ENTRY_SIZE];
+    entry[0..8].copy_from_slice(&occ.to_le_bytes());
+    entry[8..24].copy_from_slice(&pcs);
+    entry[24..26].copy_from_slice(&score.to_le_bytes());
+    entry[26] = (2.0 * result) as u8;
+    entry[27] = (ours & kings).trailing_zeros() as u8;
+    entry[28] = (theirs & kings).trailing_zeros() as u8 ^ 56;
+    entry
+}
+
+/// Decodes a bulletformat `ChessBoard` into the board, score and result
+/// The board is from the side to move's point of view, so it always has white
+/// to move, and it has no castling rights or en passant square
+pub fn from_chess_board(entry: &[u8; ENTRY_SIZE]) -> Option<(Board, i16, f32)> {
+    let mut builder = BoardBuilder::empty();
+    let mut occ = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+    let mut i = 0;
+    while occ != 0 {
+        let sq = Square::index(occ.trailing_zeros() as usize);
+        let nibble = usize::from(entry[8 + i / 2] >> (4 * (i & 1))) & 0xF;
+        let color = if nibble & 8 == 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        *builder.square_mut(sq) = Some((*Piece::ALL.get(nibble & 7)?, color));
+        i += 1;
+        occ &= occ - 1;
+    }
+
+    let board = builder.build().ok()?;
+    let score = i16::from_le_bytes([entry[24], entry[25]]);
+    Some((board, score, f32::from(entry[26]) / 2.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Positions are encoded from the side to move's point of view
+    fn test_chess_board_encoding() {
+        let board = Board::from_fen(STARTPOS, false).unwrap();
+        let entry = to_chess_board(&board, 25, 1.0);
+        assert_eq!(entry[0..8], 0xFFFF_0000_0000_FFFFu64.to_le_bytes());
+        // White rook, knight, bishop and queen on a1-d1, black knight and rook on g8-h8
+        assert_eq!(entry[8..10], [0x13, 0x42]);
+        assert_eq!(entry[23], 0xB9);
+        assert_eq!(entry[24..29], [25, 0, 2, 4, 4]);
+
+        // With black to move, black's pieces come first after flipping
+        let mut board = board;
+        board.play("e2e4".parse().unwrap());
+        let entry = to_chess_board(&board, -25, 0.0);
+        assert_eq!(entry[8..10], [0x13, 0x42]);
+        assert_eq!(entry[24..29], [0xE7, 0xFF, 0, 4, 4]);
+
+        let (decoded, score, result) = from_chess_board(&entry).unwrap();
+        assert_eq!(
+            decoded.to_string(),
+            "rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w - - 0 1"
+        );
+        assert_eq!((score, result), (-25, 0.0));
+    }
+}
@@ -0,0 +1,346 @@
+//! The engine as a library
+//!
+//! `Engine` is what the UCI frontend is built on. It holds the position and
+//! the options, and its searches return a `SearchResult` instead of printing.
+//!
+//! ```no_run
+//! use tsunami::engine::{Engine, SearchLimits};
+//!
+//! let mut engine = Engine::new();
+//! engine.set_position(tsunami::STARTPOS, &["e2e4", "e7e5"]).unwrap();
+//! let result = engine.search(&SearchLimits {
+//!     depth: 6,
+//!     ..SearchLimits::default()
+//! });
+//! println!("{:?} {}", result.best_move, result.eval);
+//! ```
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use cozy_chess::{Board, Move};
+
+use crate::{
+    chess960,
+    error::Error,
+    nnue::{self, Network},
+    position::Position,
+    search::{
+        report::{SearchReporter, SilentReporter},
+        search_until, SearchResult,
+    },
+    MAX_DEPTH, STARTPOS,
+};
+
+/// When a search stops, whichever limit is reached first. The search then
+/// returns the result of its last completed iteration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: u8,
+    pub nodes: u64,
+    pub time_millis: u128,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self {
+            depth: MAX_DEPTH,
+            nodes: u64::MAX,
+            time_millis: u128::MAX,
+        }
+    }
+}
+
+/// A position with its own network and options, engines don't share any
+/// state. Only the search parameters of builds with the `tune` feature are
+/// global, setting one affects every engine in the process.
+pub struct Engine {
+    pub(crate) pos: Position,
+    /// Castling is written as king takes rook in UCI
+    pub(crate) chess960: bool,
+    stop: Arc<Stop>,
+}
+
+/// Stops the search that is running, if there is one
+#[derive(Debug, Default)]
+struct Stop {
+    flag: AtomicBool,
+    /// Whether a search is running, the flag is only set while one is
+    searching: Mutex<bool>,
+}
+
+impl Stop {
+    fn stop(&self) {
+        let searching = self.searching.lock().unwrap();
+        if *searching {
+            self.flag.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn set_searching(&self, searching: bool) {
+        *self.searching.lock().unwrap() = searching;
+        self.flag.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self {
+            pos: Position::from_fen(STARTPOS),
+            chess960: false,
+            stop: Arc::new(Stop::default()),
+        }
+    }
+
+    /// Sets up a position from a FEN and moves in UCI notation played from it
+    /// The previous position is kept if either is invalid
+    pub fn set_position(&mut self, fen: &str, moves: &[&str]) -> Result<(), Error> {
+        let mut pos = Position::try_from_fen_with(fen, Arc::clone(self.pos.network()))?;
+        pos.classical = self.pos.classical;
+        play_uci(&mut pos, moves, self.chess960)?;
+        self.pos = pos;
+        Ok(())
+    }
+
+    /// Plays moves in UCI notation from the current position, none of them
+    /// are played if one is invalid
+    pub fn play_uci(&mut self, moves: &[&str]) -> Result<(), Error> {
+        let mut pos = self.pos.clone();
+        play_uci(&mut pos, moves, self.chess960)?;
+        self.pos = pos;
+        Ok(())
+    }
+
+    /// Plays a legal move in the current position
+    pub fn play(&mut self, mv: Move) -> Result<(), Error> {
+        self.pos.try_make_move(mv)
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.pos.board
+    }
+
+    /// The static evaluation of the current position, for the side to move
+    pub fn eval(&self) -> i32 {
+        self.pos.eval()
+    }
+
+    /// The network of this engine, the embedded one unless `EvalFile` is set
+    pub fn network(&self) -> &Arc<Network> {
+        self.pos.network()
+    }
+
+    pub fn chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /// Searches the current position without reporting progress
+    pub fn search(&mut self, limits: &SearchLimits) -> SearchResult {
+        self.search_with(limits, &mut SilentReporter)
+    }
+
+    /// Searches the current position, reporting progress to `reporter`
+    pub fn search_with(
+        &mut self,
+        limits: &SearchLimits,
+        reporter: &mut impl SearchReporter,
+    ) -> SearchResult {
+        self.stop.set_searching(true);
+        let result = search_until(
+            &mut self.pos,
+            limits.depth,
+            limits.time_millis,
+            limits.nodes,
+            &self.stop.flag,
+            reporter,
+        );
+        self.stop.set_searching(false);
+        result
+    }
+
+    /// Stops the search in progress, which then returns the result of its
+    /// last completed iteration. Without a search in progress it does nothing.
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    /// Stops searches from another thread while this one is searching
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle(Arc::clone(&self.stop))
+    }
+
+    /// Sets one of the options the UCI frontend exposes for the engine itself
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let invalid = || Error::InvalidValue {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        match name {
+            "EvalFile" => {
+                let net = if value.is_empty() || value == nnue::EMBEDDED_NAME {
+                    nnue::embedded_network()
+                } else {
+                    Arc::new(Network::from_file(value).map_err(|err| Error::Network {
+                        path: value.to_string(),
+                        err,
+                    })?)
+                };
+                // The previous network is dropped with the last position
+                // using it
+                self.pos.set_network(net);
+            }
+            "Evaluation" => match value {
+                "NNUE" => self.pos.classical = false,
+                "Classical" => self.pos.classical = true,
+                _ => return Err(invalid()),
+            },
+            "UCI_Chess960" => self.chess960 = value == "true",
+            #[cfg(feature = "tune")]
+            name if crate::tunable::TUNABLES.iter().any(|t| t.name == name) => {
+                crate::tunable::set(name, value.parse().map_err(|_| invalid())?);
+            }
+            _ => return Err(Error::UnknownOption(name.to_string())),
+        }
+        Ok(())
+    }
+}
+
+fn play_uci(pos: &mut Position, moves: &[&str], chess960: bool) -> Result<(), Error> {
+    for &mv in moves {
+        let parsed = mv.parse().map_err(|_| Error::InvalidMove(mv.to_string()))?;
+        pos.try_make_move(chess960::from_uci(&pos.board, parsed, chess960))
+            .map_err(|_| Error::IllegalMove(mv.to_string()))?;
+    }
+    Ok(())
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stops the searches of an `Engine` from any thread
+#[derive(Clone, Debug)]
+pub struct StopHandle(Arc<Stop>);
+
+impl StopHandle {
+    /// See `Engine::stop`
+    pub fn stop(&self) {
+        self.0.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_engine() {
+        let mut engine = Engine::new();
+        assert!(engine.set_position(STARTPOS, &["e2e4", "e2e4"]).is_err());
+        assert_eq!(engine.board(), &Board::default());
+
+        // Scholar's mate in one
+        engine
+            .set_position(
+                "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+                &[],
+            )
+            .unwrap();
+        let result = engine.search(&SearchLimits {
+            depth: 3,
+            ..SearchLimits::default()
+        });
+        assert_eq!(result.best_move, Some("h5f7".parse().unwrap()));
+        assert_eq!(result.depth, 3);
+        assert_eq!(result.pv.first(), result.best_move.as_ref());
+        assert!(result.nodes > 0);
+
+        assert!(matches!(
+            engine.set_option("Hash", "16"),
+            Err(Error::UnknownOption(_))
+        ));
+
+        // A search without limits still returns once stopped
+        engine.set_position(STARTPOS, &[]).unwrap();
+        let stop = engine.stop_handle();
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            stop.stop();
+        });
+        let result = engine.search(&SearchLimits::default());
+        stopper.join().unwrap();
+        assert!(result.best_move.is_some());
+        assert!(result.depth < MAX_DEPTH);
+
+        // A stop while no search is running, such as one arriving just after
+        // a search finished, doesn't affect the next search
+        engine.stop();
+        assert_eq!(
+            engine
+                .search(&SearchLimits {
+                    depth: 3,
+                    ..SearchLimits::default()
+                })
+                .depth,
+            3
+        );
+    }
+
+    #[test]
+    fn test_engines_are_independent() {
+        let mut classical = Engine::new();
+        let mut loaded = Engine::new();
+        for engine in [&mut classical, &mut loaded] {
+            engine.set_position(STARTPOS, &["e2e4"]).unwrap();
+        }
+        let eval = loaded.eval();
+
+        classical.set_option("Evaluation", "Classical").unwrap();
+        assert_eq!(classical.eval(), crate::hce::evaluate(classical.board()));
+        assert_ne!(classical.eval(), eval);
+        assert_eq!(loaded.eval(), eval);
+
+        // A network loaded by one engine is dropped once it is replaced
+        loaded.set_option("EvalFile", "resources/net.bin").unwrap();
+        assert!(!Arc::ptr_eq(loaded.network(), classical.network()));
+        assert_eq!(loaded.eval(), eval);
+        let net = Arc::downgrade(loaded.network());
+        loaded.set_option("EvalFile", "").unwrap();
+        assert!(net.upgrade().is_none());
+        assert!(Arc::ptr_eq(loaded.network(), classical.network()));
+    }
+
+    #[test]
+    fn test_limits_stop_iterations() {
+        let mut engine = Engine::new();
+        engine
+            .set_position(
+                "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1B1PPP/R2QKB1R w KQ - 0 8",
+                &[],
+            )
+            .unwrap();
+        let start = Instant::now();
+        let result = engine.search(&SearchLimits {
+            time_millis: 200,
+            ..SearchLimits::default()
+        });
+        assert!(start.elapsed() < Duration::from_millis(1000));
+        assert!(result.best_move.is_some());
+
+        let result = engine.search(&SearchLimits {
+            nodes: 20000,
+            ..SearchLimits::default()
+        });
+        assert_eq!(result.nodes, 20000);
+        assert!(result.best_move.is_some());
+    }
+}
@@ -0,0 +1,353 @@
+//! Test suites in EPD
+//!
+//! Every position is searched with the same limits and the best move is checked
+//! against the `bm` (best moves) and `am` (moves to avoid) operations. STS
+//! suites also give points to several moves in a `c0` operation such as
+//! `"Nf3=10, e4=7"`, out of 10 per position. Lines with none of these, or
+//! without a legal move, are invalid.
+
+use std::{fmt, fs, io, time::Duration};
+
+use cozy_chess::{Board, Move};
+
+use crate::{
+    chess960,
+    clock::Instant,
+    position::{legal_moves, Position},
+    san::{parse_san, to_san},
+    search::{search, SearchInfo, SearchResult},
+    MAX_DEPTH,
+};
+
+/// Points of the best move of an STS position
+const MAX_POINTS: u32 = 10;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EpdError {
+    InvalidFen(String),
+    InvalidMove(String),
+    /// A `c0` operation that isn't a list of `move=points`
+    InvalidPoints(String),
+    /// None of `bm`, `am` or `c0`, so any move would pass
+    NothingExpected,
+    NoLegalMoves,
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpdError::InvalidFen(fen) => write!(f, "invalid FEN {}", fen),
+            EpdError::InvalidMove(mv) => write!(f, "invalid move {}", mv),
+            EpdError::InvalidPoints(points) => write!(f, "invalid points {}", points),
+            EpdError::NothingExpected => write!(f, "no bm, am or c0 operation"),
+            EpdError::NoLegalMoves => write!(f, "no legal moves"),
+        }
+    }
+}
+
+/// A position of a test suite and what is expected of it
+#[derive(Clone, Debug)]
+pub struct EpdPosition {
+    pub board: Board,
+    pub id: Option<String>,
+    pub best_moves: Vec<Move>,
+    pub avoid_moves: Vec<Move>,
+    /// STS points of each move
+    pub points: Vec<(Move, u32)>,
+}
+
+impl EpdPosition {
+    pub fn parse(line: &str) -> Result<Self, EpdError> {
+        let fields: Vec<&str> = line.split_whitespace().take(4).collect();
+        let fen = format!("{} 0 1", fields.join(" "));
+        let board = chess960::parse_fen(&fen).map_err(|_| EpdError::InvalidFen(fen.clone()))?;
+        if legal_moves(&board).is_empty() {
+            return Err(EpdError::NoLegalMoves);
+        }
+
+        let mut pos = Self {
+            board,
+            id: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            points: Vec::new(),
+        };
+
+        let operations = line
+            .splitn(5, char::is_whitespace)
+            .nth(4)
+            .unwrap_or_default();
+        for operation in split_operations(operations) {
+            let (opcode, operands) = operation.split_once(' ').unwrap_or((operation, ""));
+            let operands = operands.trim();
+            let moves = |operands: &str| -> Result<Vec<Move>, EpdError> {
+                operands
+                    .split_whitespace()
+                    .map(|san| {
+                        parse_san(&pos.board, san).map_err(|_| EpdError::InvalidMove(san.into()))
+                    })
+                    .collect()
+            };
+            match opcode {
+                "id" => pos.id = Some(operands.trim_matches('"').to_string()),
+                "bm" => pos.best_moves = moves(operands)?,
+                "am" => pos.avoid_moves = moves(operands)?,
+                "c0" if operands.contains('=') => {
+                    pos.points = operands
+                        .trim_matches('"')
+                        .split(',')
+                        .map(|entry| {
+                            let invalid = || EpdError::InvalidPoints(entry.to_string());
+                            let (san, points) = entry.trim().split_once('=').ok_or_else(invalid)?;
+                            let mv = parse_san(&pos.board, san)
+                                .map_err(|_| EpdError::InvalidMove(san.into()))?;
+                            Ok((mv, points.parse().map_err(|_| invalid())?))
+                        })
+                        .collect::<Result<_, _>>()?;
+                }
+                _ => {}
+            }
+        }
+
+        if pos.best_moves.is_empty() && pos.avoid_moves.is_empty() && pos.points.is_empty() {
+            return Err(EpdError::NothingExpected);
+        }
+        Ok(pos)
+    }
+
+    /// The points a move scores, out of `MAX_POINTS` for STS positions and 1
+    /// otherwise
+    pub fn score(&self, mv: Move) -> u32 {
+        if !self.points.is_empty() {
+            return self
+                .points
+                .iter()
+                .find(|&&(m, _)| m == mv)
+                .map_or(0, |&(_, points)| points);
+        }
+        let best = self.best_moves.is_empty() || self.best_moves.contains(&mv);
+        let avoided = !self.avoid_moves.contains(&mv);
+        u32::from(best && avoided)
+    }
+
+    pub fn max_score(&self) -> u32 {
+        if self.points.is_empty() {
+            1
+        } else {
+            MAX_POINTS
+        }
+    }
+}
+
+/// Splits operations on the semicolons that aren't in quotes
+fn split_operations(operations: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (i, c) in operations.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                result.push(operations[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(operations[start..].trim());
+    result.retain(|op| !op.is_empty());
+    result
+}
+
+#[derive(Clone, Debug)]
+pub struct EpdOptions {
+    pub files: Vec<String>,
+    pub depth: u8,
+    pub nodes: u64,
+    pub time_millis: u128,
+}
+
+impl Default for EpdOptions {
+    fn default() -> Self {
+        Self {
+            files: Vec::new(),
+            depth: MAX_DEPTH,
+            nodes: u64::MAX,
+            time_millis: u128::MAX,
+        }
+    }
+}
+
+/// Searches every position of the suites and prints the results
+pub fn run(options: &EpdOptions) -> io::Result<()> {
+    let (mut total, mut solved, mut score, mut max_score) = (0, 0, 0, 0);
+    let mut total_nodes = 0;
+    let start_time = Instant::now();
+
+    for path in &options.files {
+        let text = fs::read_to_string(path)?;
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let epd = match EpdPosition::parse(line) {
+                Ok(epd) => epd,
+                Err(err) => {
+                    println!("{}:{}: {}", path, i + 1, err);
+                    continue;
+                }
+            };
+
+            let (result, solved_at) = solve(&epd, options);
+            total_nodes += result.nodes;
+            // Positions have legal moves, and the first iteration always
+            // completes
+            let mv = result.best_move.unwrap();
+
+            let points = epd.score(mv);
+            total += 1;
+            score += points;
+            max_score += epd.max_score();
+            if points == epd.max_score() {
+                solved += 1;
+            }
+
+            let expected = if !epd.points.is_empty() {
+                let (best, _) = epd.points.iter().max_by_key(|(_, points)| *points).unwrap();
+                format!("c0 {}", to_san(&epd.board, *best))
+            } else if !epd.best_moves.is_empty() {
+                format!("bm {}", san_list(&epd.board, &epd.best_moves))
+            } else {
+                format!("am {}", san_list(&epd.board, &epd.avoid_moves))
+            };
+            let outcome = match solved_at {
+                Some((time, depth)) => {
+                    format!("solved in {:.2}s depth {}", time.as_secs_f64(), depth)
+                }
+                None => String::from("failed"),
+            };
+            println!(
+                "{:<24} {:<8} {:<20} {:>2}/{:<2} {}",
+                epd.id.as_deref().unwrap_or(&format!("{}:{}", path, i + 1)),
+                to_san(&epd.board, mv),
+                expected,
+                points,
+                epd.max_score(),
+                outcome
+            );
+        }
+    }
+
+    let elapsed = start_time.elapsed().as_secs_f64();
+    println!();
+    println!(
+        "Solved {}/{} ({:.1}%)",
+        solved,
+        total,
+        100.0 * solved as f64 / total.max(1) as f64
+    );
+    println!(
+        "Score {}/{} ({:.1}%)",
+        score,
+        max_score,
+        100.0 * score as f64 / max_score.max(1) as f64
+    );
+    println!(
+        "Time {:.2}s nodes {} nps {:.0}",
+        elapsed,
+        total_nodes,
+        total_nodes as f64 / elapsed
+    );
+    Ok(())
+}
+
+/// Searches a position, returning the result and the time and depth the best
+/// move last changed to one that scores full points
+fn solve(epd: &EpdPosition, options: &EpdOptions) -> (SearchResult, Option<(Duration, u8)>) {
+    let mut pos = Position::from_fen(&epd.board.to_string());
+    let start = Instant::now();
+    let mut solved_at = None;
+    let result = search(
+        &mut pos,
+        options.depth,
+        options.time_millis,
+        options.nodes,
+        &mut |info: &SearchInfo| {
+            let full = info
+                .best_move
+                .is_some_and(|mv| epd.score(mv) == epd.max_score());
+            match (full, solved_at) {
+                (true, None) => solved_at = Some((start.elapsed(), info.depth)),
+                (false, _) => solved_at = None,
+                _ => {}
+            }
+        },
+    );
+    (result, solved_at)
+}
+
+fn san_list(board: &Board, moves: &[Move]) -> String {
+    moves
+        .iter()
+        .map(|&mv| to_san(board, mv))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_epd() {
+        let wac = EpdPosition::parse(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";",
+        )
+        .unwrap();
+        assert_eq!(wac.id.as_deref(), Some("WAC.001"));
+        assert_eq!(wac.best_moves, ["g3g6".parse().unwrap()]);
+        assert_eq!(wac.score("g3g6".parse().unwrap()), 1);
+        assert_eq!(wac.score("f1f7".parse().unwrap()), 0);
+
+        let sts = EpdPosition::parse(
+            "1kr5/3n4/q3p2p/p2n2p1/PppB1P2/5BP1/1P2Q2P/3R2K1 w - - bm f5; id \"STS(v1.0) \
+             Undermine.001\"; c0 \"f5=10, Be5+=2, Bf2=3, Bg4=2\";",
+        )
+        .unwrap();
+        assert_eq!(sts.points.len(), 4);
+        assert_eq!(sts.max_score(), 10);
+        assert_eq!(sts.score("f4f5".parse().unwrap()), 10);
+        assert_eq!(sts.score("d4f2".parse().unwrap()), 3);
+        assert_eq!(sts.score("e2e1".parse().unwrap()), 0);
+
+        let am = EpdPosition::parse("8/8/8/8/8/8/4k3/K7 b - - am Kd2;").unwrap();
+        assert_eq!(am.score("e2d2".parse().unwrap()), 0);
+        assert_eq!(am.score("e2e3".parse().unwrap()), 1);
+
+        // Lines that any move would pass, or that have no move to check
+        assert_eq!(
+            EpdPosition::parse("8/8/8/8/8/8/4k3/K7 b - - id \"none\"; c0 \"comment\";")
+                .unwrap_err(),
+            EpdError::NothingExpected
+        );
+        assert_eq!(
+            EpdPosition::parse("7k/5Q2/6K1/8/8/8/8/8 b - - am Kg8;").unwrap_err(),
+            EpdError::NoLegalMoves
+        );
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        // Only a depth is given, the search isn't cut short by a default time
+        let epd = EpdPosition::parse(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - bm Qxf6; id \"kiwipete\";",
+        )
+        .unwrap();
+        let options = EpdOptions {
+            depth: 7,
+            ..EpdOptions::default()
+        };
+        let (result, _) = solve(&epd, &options);
+        assert_eq!(result.depth, 7);
+    }
+}
@@ -0,0 +1,44 @@
+//! Errors of input from the GUI, files and the command line
+
+use std::fmt;
+
+use crate::{book::BookError, nnue::LoadError};
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidFen(String),
+    /// Not a move in UCI notation
+    InvalidMove(String),
+    /// A move that can't be played in the position
+    IllegalMove(String),
+    /// The value of a command argument or option that can't be used
+    InvalidValue {
+        name: String,
+        value: String,
+    },
+    UnknownOption(String),
+    Book {
+        path: String,
+        err: BookError,
+    },
+    Network {
+        path: String,
+        err: LoadError,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidFen(fen) => write!(f, "invalid FEN {}", fen),
+            Error::InvalidMove(mv) => write!(f, "invalid move {}", mv),
+            Error::IllegalMove(mv) => write!(f, "illegal move {}", mv),
+            Error::InvalidValue { name, value } => {
+                write!(f, "invalid value '{}' for {}", value, name)
+            }
+            Error::UnknownOption(name) => write!(f, "unknown option {}", name),
+            Error::Book { path, err } => write!(f, "loading {}: {}", path, err),
+            Error::Network { path, err } => write!(f, "loading {}: {}", path, err),
+        }
+    }
+}
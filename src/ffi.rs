@@ -0,0 +1,322 @@
+//! C API over `Engine`, declared in `include/tsunami.h`
+//!
+//! Functions that can fail return 0 on success and -1 otherwise, the message
+//! of the last error of the calling thread is kept for `tsunami_last_error`.
+//! Moves are written in UCI notation into fixed buffers, so the caller never
+//! frees strings made by the engine. Panics are caught at the boundary and
+//! reported like errors.
+//!
+//! The header is generated from this file by cbindgen, with the configuration
+//! in `cbindgen.toml`. `tests/ffi.rs` checks that it is up to date and rewrites
+//! it when `UPDATE_HEADER` is set.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use cozy_chess::{Board, Move};
+
+use crate::{
+    chess960,
+    engine::{Engine, SearchLimits},
+    perft,
+    search::{
+        report::{uci_moves, SearchReporter},
+        SearchInfo,
+    },
+};
+
+/// Long enough for a UCI move such as `e7e8q` and the terminating zero
+pub const TSUNAMI_MOVE_LEN: usize = 8;
+
+pub struct TsunamiEngine(Engine);
+
+#[repr(C)]
+pub struct TsunamiSearchResult {
+    /// In UCI notation, empty when there is no legal move
+    pub best_move: [c_char; TSUNAMI_MOVE_LEN],
+    /// Empty when the search has no reply to the best move
+    pub ponder_move: [c_char; TSUNAMI_MOVE_LEN],
+    /// In centipawns for the side to move
+    pub eval: c_int,
+    pub depth: c_int,
+    pub nodes: u64,
+    pub time_ms: u64,
+}
+
+/// Called after every completed iteration with its principal variation, as
+/// UCI moves separated by spaces. The string only lives for the call.
+pub type TsunamiInfoCallback = Option<
+    extern "C" fn(depth: c_int, eval: c_int, nodes: u64, pv: *const c_char, user_data: *mut c_void),
+>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(err: impl ToString) -> c_int {
+    let msg = CString::new(err.to_string()).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(msg));
+    -1
+}
+
+/// Runs the body of an exported function that can fail, for its status
+fn status(body: impl FnOnce() -> Result<(), c_int>) -> c_int {
+    guard(-1, || body().map_or_else(|err| err, |()| 0))
+}
+
+/// Runs the body of an exported function, a panic is reported as an error
+/// and returns `on_panic` instead of unwinding into the caller
+fn guard<T>(on_panic: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let msg = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown cause");
+        set_error(format!("panic: {}", msg));
+        on_panic
+    })
+}
+
+/// The engine behind a pointer from `tsunami_new`, null pointers are errors
+unsafe fn engine<'a>(engine: *mut TsunamiEngine) -> Result<&'a mut Engine, c_int> {
+    engine
+        .as_mut()
+        .map(|engine| &mut engine.0)
+        .ok_or_else(|| set_error("null engine"))
+}
+
+unsafe fn string<'a>(s: *const c_char) -> Result<&'a str, c_int> {
+    if s.is_null() {
+        return Err(set_error("null string"));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| set_error("string is not UTF-8"))
+}
+
+fn write_move(
+    buf: &mut [c_char; TSUNAMI_MOVE_LEN],
+    board: &Board,
+    mv: Option<Move>,
+    chess960: bool,
+) {
+    *buf = [0; TSUNAMI_MOVE_LEN];
+    if let Some(mv) = mv {
+        let uci = chess960::to_uci(board, mv, chess960).to_string();
+        for (dst, src) in buf.iter_mut().zip(uci.bytes()) {
+            *dst = src as c_char;
+        }
+    }
+}
+
+struct CallbackReporter {
+    callback: TsunamiInfoCallback,
+    user_data: *mut c_void,
+    board: Board,
+    chess960: bool,
+    /// Of the completed iterations
+    nodes: u64,
+}
+
+impl SearchReporter for CallbackReporter {
+    fn iteration(&mut self, info: &SearchInfo) {
+        self.nodes += info.nodes;
+        if let Some(callback) = self.callback {
+            let pv = uci_moves(&self.board, &info.pv, self.chess960).join(" ");
+            let pv = CString::new(pv).unwrap_or_default();
+            callback(
+                info.depth.into(),
+                info.eval,
+                self.nodes,
+                pv.as_ptr(),
+                self.user_data,
+            );
+        }
+    }
+}
+
+/// A new engine at the starting position, to be freed with `tsunami_free`
+#[no_mangle]
+pub extern "C" fn tsunami_new() -> *mut TsunamiEngine {
+    guard(ptr::null_mut(), || {
+        Box::into_raw(Box::new(TsunamiEngine(Engine::new())))
+    })
+}
+
+/// # Safety
+/// `engine` is null or comes from `tsunami_new` and hasn't been freed
+#[no_mangle]
+pub unsafe extern "C" fn tsunami_free(engine: *mut TsunamiEngine) {
+    guard((), || {
+        if !engine.is_null() {
+            drop(Box::from_raw(engine));
+        }
+    })
+}
+
+/// The message of the last error on this thread, or null. It lives until the
+/// next error on the thread.
+#[no_mangle]
+pub extern "C" fn tsunami_last_error() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|last| {
+            last.borrow()
+                .as_ref()
+                .map_or(ptr::null(), |msg| msg.as_ptr())
+        })
+    })
+}
+
+/// Sets up an X-FEN or Shredder-FEN, the position is unchanged on error
+///
+/// # Safety
+/// `engine` comes from `tsunami_new`, `fen` is a zero-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn tsunami_set_fen(engine: *mut TsunamiEngine, fen: *const c_char) -> c_int {
+    status(|| {
+        let engine = self::engine(engine)?;
+        engine.set_position(string(fen)?, &[]).map_err(set_error)
+    })
+}
+
+/// Plays UCI moves separated by spaces, none of them if one is invalid
+///
+/// # Safety
+/// `engine` comes from `tsunami_new`, `moves` is a zero-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn tsunami_play_moves(
+    engine: *mut TsunamiEngine,
+    moves: *const c_char,
+) -> c_int {
+    status(|| {
+        let engine = self::engine(engine)?;
+        let moves: Vec<&str> = string(moves)?.split_whitespace().collect();
+        engine.play_uci(&moves).map_err(set_error)
+    })
+}
+
+/// Sets `EvalFile`, `Evaluation` or `UCI_Chess960` of this engine only
+///
+/// # Safety
+/// `engine` comes from `tsunami_new`, `name` and `value` are zero-terminated
+/// strings
+#[no_mangle]
+pub unsafe extern "C" fn tsunami_set_option(
+    engine: *mut TsunamiEngine,
+    name: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    status(|| {
+        let engine = self::engine(engine)?;
+        engine
+            .set_option(string(name)?, string(value)?)
+            .map_err(set_error)
+    })
+}
+
+/// Searches the current position until one of the limits is reached, a limit
+/// of 0 is no limit. `callback` may be null.
+///
+/// # Safety
+/// `engine` comes from `tsunami_new` and `result` points to a writable
+/// `TsunamiSearchResult`
+#[no_mangle]
+pub unsafe extern "C" fn tsunami_search(
+    engine: *mut TsunamiEngine,
+    depth: c_int,
+    nodes: u64,
+    time_ms: u64,
+    callback: TsunamiInfoCallback,
+    user_data: *mut c_void,
+    result: *mut TsunamiSearchResult,
+) -> c_int {
+    guard(-1, || {
+        let Ok(engine) = self::engine(engine) else {
+            return -1;
+        };
+        let Some(result) = result.as_mut() else {
+            return set_error("null result");
+        };
+
+        let defaults = SearchLimits::default();
+        let limits = SearchLimits {
+            depth: u8::try_from(depth)
+                .ok()
+                .filter(|&depth| depth > 0)
+                .map_or(defaults.depth, |depth| depth.min(defaults.depth)),
+            nodes: if nodes == 0 { defaults.nodes } else { nodes },
+            time_millis: if time_ms == 0 {
+                defaults.time_millis
+            } else {
+                time_ms.into()
+            },
+        };
+        let board = engine.board().clone();
+        let chess960 = engine.chess960();
+        let mut reporter = CallbackReporter {
+            callback,
+            user_data,
+            board: board.clone(),
+            chess960,
+            nodes: 0,
+        };
+        let search = engine.search_with(&limits, &mut reporter);
+
+        write_move(&mut result.best_move, &board, search.best_move, chess960);
+        let mut after = board.clone();
+        if let Some(mv) = search.best_move {
+            after.play_unchecked(mv);
+        }
+        write_move(
+            &mut result.ponder_move,
+            &after,
+            search.ponder_move,
+            chess960,
+        );
+        result.eval = search.eval;
+        result.depth = search.depth.into();
+        result.nodes = search.nodes;
+        result.time_ms = search.time_millis.try_into().unwrap_or(u64::MAX);
+        0
+    })
+}
+
+/// The static evaluation of the current position in centipawns, for the side
+/// to move
+///
+/// # Safety
+/// `engine` comes from `tsunami_new`
+#[no_mangle]
+pub unsafe extern "C" fn tsunami_eval(engine: *const TsunamiEngine) -> c_int {
+    guard(0, || engine.as_ref().map_or(0, |engine| engine.0.eval()))
+}
+
+/// The number of leaf nodes of the current position at `depth`
+///
+/// # Safety
+/// `engine` comes from `tsunami_new`
+#[no_mangle]
+pub unsafe extern "C" fn tsunami_perft(engine: *const TsunamiEngine, depth: c_int) -> u64 {
+    guard(0, || match (engine.as_ref(), u8::try_from(depth)) {
+        (Some(engine), Ok(depth)) => perft::perft(engine.0.board(), depth),
+        _ => 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guard() {
+        assert_eq!(guard(-1, || panic!("boom")), -1);
+        let msg = unsafe { CStr::from_ptr(tsunami_last_error()) };
+        assert_eq!(msg.to_str().unwrap(), "panic: boom");
+        assert_eq!(status(|| Ok(())), 0);
+    }
+}
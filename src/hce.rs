@@ -0,0 +1,576 @@
+//! Hand-crafted evaluation
+//!
+//! A classical tapered evaluation, used instead of the network when the
+//! `Evaluation` option is set to `Classical`. Every term has a middlegame and
+//! an endgame score, which are blended by the amount of material left.
+//!
+//! The evaluation is written as a list of features (a parameter and how often
+//! it applies to a side), so the same code produces the score and the trace
+//! printed by `eval trace`.
+
+use std::{
+    fmt,
+    ops::{Add, AddAssign, Mul, Neg, Sub},
+};
+
+use cozy_chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves, BitBoard,
+    Board, Color, File, Piece, Rank, Square,
+};
+
+/// Phase of each piece, the starting position has `MAX_PHASE`
+const PHASE_VALS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const MAX_PHASE: i32 = 24;
+
+/// A middlegame and an endgame score
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct S(pub i32, pub i32);
+
+impl S {
+    /// Blends the two scores by the game phase
+    pub fn taper(self, phase: i32) -> i32 {
+        (self.0 * phase + self.1 * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for S {
+    type Output = S;
+
+    fn add(self, rhs: S) -> S {
+        S(self.0 + rhs.0, self.1 + rhs.1)
+    }
+}
+
+impl AddAssign for S {
+    fn add_assign(&mut self, rhs: S) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for S {
+    type Output = S;
+
+    fn sub(self, rhs: S) -> S {
+        S(self.0 - rhs.0, self.1 - rhs.1)
+    }
+}
+
+impl Neg for S {
+    type Output = S;
+
+    fn neg(self) -> S {
+        S(-self.0, -self.1)
+    }
+}
+
+impl Mul<i32> for S {
+    type Output = S;
+
+    fn mul(self, rhs: i32) -> S {
+        S(self.0 * rhs, self.1 * rhs)
+    }
+}
+
+const MATERIAL: [S; 6] = [
+    S(82, 94),
+    S(337, 281),
+    S(365, 297),
+    S(477, 512),
+    S(1025, 936),
+    S(0, 0),
+];
+const BISHOP_PAIR: S = S(30, 50);
+
+/// Pieces with mobility and king attack terms
+pub const ATTACKERS: [Piece; 4] = [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
+
+/// Mobility per attacked square, for knights, bishops, rooks and queens
+const MOBILITY: [S; 4] = [S(4, 4), S(5, 5), S(2, 4), S(1, 2)];
+
+const DOUBLED_PAWN: S = S(-10, -20);
+const ISOLATED_PAWN: S = S(-10, -10);
+/// By the rank of the pawn, from its own side
+const PASSED_PAWN: [S; 8] = [
+    S(0, 0),
+    S(5, 10),
+    S(5, 15),
+    S(10, 25),
+    S(20, 45),
+    S(35, 70),
+    S(55, 110),
+    S(0, 0),
+];
+
+/// Pawns on the two ranks in front of the king
+const PAWN_SHIELD: S = S(15, 0);
+/// Per square around the king attacked by a knight, bishop, rook or queen
+const KING_ATTACK: [S; 4] = [S(-8, -2), S(-8, -2), S(-10, -3), S(-12, -4)];
+
+/// Piece-square tables from white's point of view, indexed from a1
+#[rustfmt::skip]
+const PSQT: [[S; 64]; 6] = [
+    // Pawn
+    [
+        S(0, 0), S(0, 0), S(0, 0), S(0, 0), S(0, 0), S(0, 0), S(0, 0), S(0, 0),
+        S(0, 0), S(0, 0), S(0, 0), S(-5, 0), S(-5, 0), S(0, 0), S(0, 0), S(0, 0),
+        S(5, 10), S(5, 10), S(5, 10), S(5, 10), S(5, 10), S(5, 10), S(5, 10), S(5, 10),
+        S(10, 20), S(10, 20), S(10, 20), S(20, 20), S(20, 20), S(10, 20), S(10, 20), S(10, 20),
+        S(20, 35), S(20, 35), S(20, 35), S(30, 35), S(30, 35), S(20, 35), S(20, 35), S(20, 35),
+        S(35, 55), S(35, 55), S(35, 55), S(35, 55), S(35, 55), S(35, 55), S(35, 55), S(35, 55),
+        S(60, 80), S(60, 80), S(60, 80), S(60, 80), S(60, 80), S(60, 80), S(60, 80), S(60, 80),
+        S(0, 0), S(0, 0), S(0, 0), S(0, 0), S(0, 0), S(0, 0), S(0, 0), S(0, 0),
+    ],
+    // Knight
+    [
+        S(-28, -21), S(-20, -15), S(-12, -9), S(-4, -3), S(-4, -3), S(-12, -9), S(-20, -15), S(-28, -21),
+        S(-20, -15), S(-12, -9), S(-4, -3), S(4, 3), S(4, 3), S(-4, -3), S(-12, -9), S(-20, -15),
+        S(-12, -9), S(-4, -3), S(4, 3), S(12, 9), S(12, 9), S(4, 3), S(-4, -3), S(-12, -9),
+        S(-4, -3), S(4, 3), S(12, 9), S(20, 15), S(20, 15), S(12, 9), S(4, 3), S(-4, -3),
+        S(-4, -3), S(4, 3), S(12, 9), S(20, 15), S(20, 15), S(12, 9), S(4, 3), S(-4, -3),
+        S(-12, -9), S(-4, -3), S(4, 3), S(12, 9), S(12, 9), S(4, 3), S(-4, -3), S(-12, -9),
+        S(-20, -15), S(-12, -9), S(-4, -3), S(4, 3), S(4, 3), S(-4, -3), S(-12, -9), S(-20, -15),
+        S(-28, -21), S(-20, -15), S(-12, -9), S(-4, -3), S(-4, -3), S(-12, -9), S(-20, -15), S(-28, -21),
+    ],
+    // Bishop
+    [
+        S(-8, -10), S(-5, -7), S(-2, -4), S(1, -1), S(1, -1), S(-2, -4), S(-5, -7), S(-8, -10),
+        S(-5, -7), S(-2, -4), S(1, -1), S(4, 2), S(4, 2), S(1, -1), S(-2, -4), S(-5, -7),
+        S(-2, -4), S(1, -1), S(4, 2), S(7, 5), S(7, 5), S(4, 2), S(1, -1), S(-2, -4),
+        S(1, -1), S(4, 2), S(7, 5), S(10, 8), S(10, 8), S(7, 5), S(4, 2), S(1, -1),
+        S(1, -1), S(4, 2), S(7, 5), S(10, 8), S(10, 8), S(7, 5), S(4, 2), S(1, -1),
+        S(-2, -4), S(1, -1), S(4, 2), S(7, 5), S(7, 5), S(4, 2), S(1, -1), S(-2, -4),
+        S(-5, -7), S(-2, -4), S(1, -1), S(4, 2), S(4, 2), S(1, -1), S(-2, -4), S(-5, -7),
+        S(-8, -10), S(-5, -7), S(-2, -4), S(1, -1), S(1, -1), S(-2, -4), S(-5, -7), S(-8, -10),
+    ],
+    // Rook
+    [
+        S(0, 0), S(0, 0), S(0, 0), S(5, 0), S(5, 0), S(0, 0), S(0, 0), S(0, 0),
+        S(0, 0), S(0, 0), S(0, 0), S(5, 0), S(5, 0), S(0, 0), S(0, 0), S(0, 0),
+        S(0, 0), S(0, 0), S(0, 0), S(5, 0), S(5, 0), S(0, 0), S(0, 0), S(0, 0),
+        S(0, 0), S(0, 0), S(0, 0), S(5, 0), S(5, 0), S(0, 0), S(0, 0), S(0, 0),
+        S(0, 0), S(0, 0), S(0, 0), S(5, 0), S(5, 0), S(0, 0), S(0, 0), S(0, 0),
+        S(0, 0), S(0, 0), S(0, 0), S(5, 0), S(5, 0), S(0, 0), S(0, 0), S(0, 0),
+        S(15, 10), S(15, 10), S(15, 10), S(20, 10), S(20, 10), S(15, 10), S(15, 10), S(15, 10),
+        S(0, 0), S(0, 0), S(0, 0), S(5, 0), S(5, 0), S(0, 0), S(0, 0), S(0, 0),
+    ],
+    // Queen
+    [
+        S(-7, -8), S(-5, -5), S(-3, -2), S(-1, 1), S(-1, 1), S(-3, -2), S(-5, -5), S(-7, -8),
+        S(-5, -5), S(-3, -2), S(-1, 1), S(1, 4), S(1, 4), S(-1, 1), S(-3, -2), S(-5, -5),
+        S(-3, -2), S(-1, 1), S(1, 4), S(3, 7), S(3, 7), S(1, 4), S(-1, 1), S(-3, -2),
+        S(-1, 1), S(1, 4), S(3, 7), S(5, 10), S(5, 10), S(3, 7), S(1, 4), S(-1, 1),
+        S(-1, 1), S(1, 4), S(3, 7), S(5, 10), S(5, 10), S(3, 7), S(1, 4), S(-1, 1),
+        S(-3, -2), S(-1, 1), S(1, 4), S(3, 7), S(3, 7), S(1, 4), S(-1, 1), S(-3, -2),
+        S(-5, -5), S(-3, -2), S(-1, 1), S(1, 4), S(1, 4), S(-1, 1), S(-3, -2), S(-5, -5),
+        S(-7, -8), S(-5, -5), S(-3, -2), S(-1, 1), S(-1, 1), S(-3, -2), S(-5, -5), S(-7, -8),
+    ],
+    // King
+    [
+        S(20, -28), S(30, -20), S(10, -12), S(0, -4), S(0, -4), S(10, -12), S(30, -20), S(20, -28),
+        S(10, -20), S(10, -12), S(-5, -4), S(-10, 4), S(-10, 4), S(-5, -4), S(10, -12), S(10, -20),
+        S(-20, -12), S(-20, -4), S(-20, 4), S(-20, 12), S(-20, 12), S(-20, 4), S(-20, -4), S(-20, -12),
+        S(-30, -4), S(-30, 4), S(-30, 12), S(-30, 20), S(-30, 20), S(-30, 12), S(-30, 4), S(-30, -4),
+        S(-40, -4), S(-40, 4), S(-40, 12), S(-40, 20), S(-40, 20), S(-40, 12), S(-40, 4), S(-40, -4),
+        S(-50, -12), S(-50, -4), S(-50, 4), S(-50, 12), S(-50, 12), S(-50, 4), S(-50, -4), S(-50, -12),
+        S(-60, -20), S(-60, -12), S(-60, -4), S(-60, 4), S(-60, 4), S(-60, -4), S(-60, -12), S(-60, -20),
+        S(-70, -28), S(-70, -20), S(-70, -12), S(-70, -4), S(-70, -4), S(-70, -12), S(-70, -20), S(-70, -28),
+    ],
+];
+
+/// The groups of terms shown by the trace
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Term {
+    Material,
+    Psqt,
+    Mobility,
+    PawnStructure,
+    KingSafety,
+}
+
+impl Term {
+    pub const ALL: [Term; 5] = [
+        Term::Material,
+        Term::Psqt,
+        Term::Mobility,
+        Term::PawnStructure,
+        Term::KingSafety,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::Psqt => "Piece-square tables",
+            Term::Mobility => "Mobility",
+            Term::PawnStructure => "Pawn structure",
+            Term::KingSafety => "King safety",
+        }
+    }
+}
+
+/// A parameter of the evaluation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Param {
+    Material(Piece),
+    BishopPair,
+    /// The square is from the side's own point of view
+    Psqt(Piece, Square),
+    Mobility(Piece),
+    DoubledPawn,
+    IsolatedPawn,
+    PassedPawn(Rank),
+    PawnShield,
+    KingAttack(Piece),
+}
+
+/// Number of parameters, see `Param::index`
+pub const PARAM_COUNT: usize = 410;
+
+impl Param {
+    /// Position of the parameter in a flat list of all of them, in the order
+    /// the constants are declared
+    pub fn index(self) -> usize {
+        match self {
+            Param::Material(pc) => pc as usize,
+            Param::BishopPair => 6,
+            Param::Psqt(pc, sq) => 7 + 64 * pc as usize + sq as usize,
+            Param::Mobility(pc) => 391 + pc as usize - 1,
+            Param::DoubledPawn => 395,
+            Param::IsolatedPawn => 396,
+            Param::PassedPawn(rank) => 397 + rank as usize,
+            Param::PawnShield => 405,
+            Param::KingAttack(pc) => 406 + pc as usize - 1,
+        }
+    }
+
+    /// Every parameter, in the order of `index`
+    pub fn all() -> Vec<Param> {
+        let mut params = Piece::ALL.map(Param::Material).to_vec();
+        params.push(Param::BishopPair);
+        for pc in Piece::ALL {
+            params.extend(Square::ALL.map(|sq| Param::Psqt(pc, sq)));
+        }
+        params.extend(ATTACKERS.map(Param::Mobility));
+        params.extend([Param::DoubledPawn, Param::IsolatedPawn]);
+        params.extend(Rank::ALL.map(Param::PassedPawn));
+        params.push(Param::PawnShield);
+        params.extend(ATTACKERS.map(Param::KingAttack));
+        params
+    }
+
+    pub fn term(self) -> Term {
+        match self {
+            Param::Material(_) | Param::BishopPair => Term::Material,
+            Param::Psqt(..) => Term::Psqt,
+            Param::Mobility(_) => Term::Mobility,
+            Param::DoubledPawn | Param::IsolatedPawn | Param::PassedPawn(_) => Term::PawnStructure,
+            Param::PawnShield | Param::KingAttack(_) => Term::KingSafety,
+        }
+    }
+
+    pub fn value(self) -> S {
+        match self {
+            Param::Material(pc) => MATERIAL[pc as usize],
+            Param::BishopPair => BISHOP_PAIR,
+            Param::Psqt(pc, sq) => PSQT[pc as usize][sq as usize],
+            Param::Mobility(pc) => MOBILITY[pc as usize - 1],
+            Param::DoubledPawn => DOUBLED_PAWN,
+            Param::IsolatedPawn => ISOLATED_PAWN,
+            Param::PassedPawn(rank) => PASSED_PAWN[rank as usize],
+            Param::PawnShield => PAWN_SHIELD,
+            Param::KingAttack(pc) => KING_ATTACK[pc as usize - 1],
+        }
+    }
+}
+
+/// Receives the features of a position
+pub trait Features {
+    /// `param` applies `count` times to `color`
+    fn add(&mut self, color: Color, param: Param, count: i32);
+}
+
+/// The score of every term for both sides
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    pub terms: [[S; 2]; 5],
+    pub phase: i32,
+}
+
+impl Features for Trace {
+    fn add(&mut self, color: Color, param: Param, count: i32) {
+        self.terms[param.term() as usize][color as usize] += param.value() * count;
+    }
+}
+
+impl Trace {
+    pub fn new(board: &Board) -> Self {
+        let mut trace = Self {
+            phase: phase(board),
+            ..Default::default()
+        };
+        features(board, &mut trace);
+        trace
+    }
+
+    /// The untapered score from white's point of view
+    pub fn total(&self) -> S {
+        self.terms
+            .iter()
+            .fold(S::default(), |total, [white, black]| {
+                total + *white - *black
+            })
+    }
+
+    /// The tapered score from white's point of view
+    pub fn score(&self) -> i32 {
+        self.total().taper(self.phase)
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = "+---------------------+-------------+-------------+-------------+";
+        writeln!(f, "{}", line)?;
+        writeln!(
+            f,
+            "| {:<19} | {:^11} | {:^11} | {:^11} |",
+            "Term", "White", "Black", "Total"
+        )?;
+        writeln!(
+            f,
+            "| {:<19} | {:>5} {:>5} | {:>5} {:>5} | {:>5} {:>5} |",
+            "", "MG", "EG", "MG", "EG", "MG", "EG"
+        )?;
+        writeln!(f, "{}", line)?;
+        for term in Term::ALL {
+            let [white, black] = self.terms[term as usize];
+            let total = white - black;
+            writeln!(
+                f,
+                "| {:<19} | {:>5} {:>5} | {:>5} {:>5} | {:>5} {:>5} |",
+                term.name(),
+                white.0,
+                white.1,
+                black.0,
+                black.1,
+                total.0,
+                total.1
+            )?;
+        }
+        writeln!(f, "{}", line)?;
+        let total = self.total();
+        writeln!(
+            f,
+            "| {:<19} | {:>11} | {:>11} | {:>5} {:>5} |",
+            "Total", "", "", total.0, total.1
+        )?;
+        writeln!(f, "{}", line)?;
+        writeln!(f, "Phase: {}/{}", self.phase, MAX_PHASE)?;
+        write!(f, "Classical eval: {}cp (white)", self.score())
+    }
+}
+
+/// The hand-crafted evaluation from the side to move's point of view
+pub fn evaluate(board: &Board) -> i32 {
+    let score = Trace::new(board).score();
+    match board.side_to_move() {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+/// How much material is left, from `MAX_PHASE` in the opening to 0
+pub fn phase(board: &Board) -> i32 {
+    let phase = Piece::ALL
+        .iter()
+        .map(|&pc| board.pieces(pc).len() as i32 * PHASE_VALS[pc as usize])
+        .sum::<i32>();
+    phase.min(MAX_PHASE)
+}
+
+/// Sends every feature of the position to `features`
+pub fn features(board: &Board, features: &mut impl Features) {
+    for color in Color::ALL {
+        material(board, color, features);
+        psqt(board, color, features);
+        mobility(board, color, features);
+        pawn_structure(board, color, features);
+        king_safety(board, color, features);
+    }
+}
+
+fn material(board: &Board, color: Color, features: &mut impl Features) {
+    for pc in Piece::ALL {
+        let count = board.colored_pieces(color, pc).len() as i32;
+        if count > 0 && pc != Piece::King {
+            features.add(color, Param::Material(pc), count);
+        }
+    }
+    if board.colored_pieces(color, Piece::Bishop).len() >= 2 {
+        features.add(color, Param::BishopPair, 1);
+    }
+}
+
+fn psqt(board: &Board, color: Color, features: &mut impl Features) {
+    for pc in Piece::ALL {
+        for sq in board.colored_pieces(color, pc) {
+            features.add(color, Param::Psqt(pc, relative(sq, color)), 1);
+        }
+    }
+}
+
+fn mobility(board: &Board, color: Color, features: &mut impl Features) {
+    // Squares defended by enemy pawns don't count
+    let area = !board.colors(color) & !pawn_attacks(board, !color);
+
+    for pc in ATTACKERS {
+        for sq in board.colored_pieces(color, pc) {
+            let count = (attacks(board, pc, sq) & area).len() as i32;
+            if count > 0 {
+                features.add(color, Param::Mobility(pc), count);
+            }
+        }
+    }
+}
+
+fn pawn_structure(board: &Board, color: Color, features: &mut impl Features) {
+    let ours = board.colored_pieces(color, Piece::Pawn);
+    let theirs = board.colored_pieces(!color, Piece::Pawn);
+
+    for file in File::ALL {
+        let on_file = (ours & file.bitboard()).len() as i32;
+        if on_file > 1 {
+            features.add(color, Param::DoubledPawn, on_file - 1);
+        }
+        if on_file > 0 && (ours & file.adjacent()).is_empty() {
+            features.add(color, Param::IsolatedPawn, on_file);
+        }
+    }
+
+    for sq in ours {
+        if (theirs & front_span(sq, color)).is_empty() {
+            let rank = sq.rank().relative_to(color);
+            features.add(color, Param::PassedPawn(rank), 1);
+        }
+    }
+}
+
+fn king_safety(board: &Board, color: Color, features: &mut impl Features) {
+    let king = board.king(color);
+    let zone = get_king_moves(king) | king.bitboard();
+
+    // Pawns on the two ranks in front of the king, on its file or next to it
+    let files = king.file().bitboard() | king.file().adjacent();
+    let shield = (1..=2)
+        .map(|offset| king.rank().relative_to(color) as usize + offset)
+        .filter(|&rank| rank < 8)
+        .fold(BitBoard::EMPTY, |bb, rank| {
+            bb | Rank::index(rank).relative_to(color).bitboard()
+        });
+    let shelter = (board.colored_pieces(color, Piece::Pawn) & files & shield).len() as i32;
+    if shelter > 0 {
+        features.add(color, Param::PawnShield, shelter);
+    }
+
+    for pc in ATTACKERS {
+        let count = board
+            .colored_pieces(!color, pc)
+            .into_iter()
+            .map(|sq| (attacks(board, pc, sq) & zone).len() as i32)
+            .sum::<i32>();
+        if count > 0 {
+            features.add(color, Param::KingAttack(pc), count);
+        }
+    }
+}
+
+fn attacks(board: &Board, pc: Piece, sq: Square) -> BitBoard {
+    let blockers = board.occupied();
+    match pc {
+        Piece::Knight => get_knight_moves(sq),
+        Piece::Bishop => get_bishop_moves(sq, blockers),
+        Piece::Rook => get_rook_moves(sq, blockers),
+        Piece::Queen => get_bishop_moves(sq, blockers) | get_rook_moves(sq, blockers),
+        Piece::King => get_king_moves(sq),
+        Piece::Pawn => unreachable!("pawn attacks depend on the colour"),
+    }
+}
+
+fn pawn_attacks(board: &Board, color: Color) -> BitBoard {
+    board
+        .colored_pieces(color, Piece::Pawn)
+        .into_iter()
+        .fold(BitBoard::EMPTY, |bb, sq| bb | get_pawn_attacks(sq, color))
+}
+
+/// The squares in front of a pawn on its file and the adjacent files
+fn front_span(sq: Square, color: Color) -> BitBoard {
+    let files = sq.file().bitboard() | sq.file().adjacent();
+    let rank = sq.rank().relative_to(color) as usize;
+    (rank + 1..8).fold(BitBoard::EMPTY, |bb, r| {
+        bb | (files & Rank::index(r).relative_to(color).bitboard())
+    })
+}
+
+/// The square from `color`'s point of view
+fn relative(sq: Square, color: Color) -> Square {
+    match color {
+        Color::White => sq,
+        Color::Black => sq.flip_rank(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Swaps the colours of a position and flips it vertically
+    fn flip(fen: &str) -> String {
+        let parts: Vec<&str> = fen.split(' ').collect();
+        let swap_case = |s: &str| {
+            s.chars()
+                .map(|c| match c.is_ascii_uppercase() {
+                    true => c.to_ascii_lowercase(),
+                    false => c.to_ascii_uppercase(),
+                })
+                .collect::<String>()
+        };
+        let placement = parts[0].split('/').rev().collect::<Vec<_>>().join("/");
+        let stm = if parts[1] == "w" { "b" } else { "w" };
+        format!(
+            "{} {} {} - 0 1",
+            swap_case(&placement),
+            stm,
+            swap_case(parts[2])
+        )
+    }
+
+    #[test]
+    // Flipping the colours of a position doesn't change its evaluation
+    fn test_symmetry() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 b - - 0 10",
+        ];
+        assert_eq!(
+            evaluate(&Board::default()),
+            0,
+            "the starting position is equal"
+        );
+        for fen in fens {
+            let board = Board::from_fen(fen, false).unwrap();
+            let flipped = Board::from_fen(&flip(fen), false).unwrap();
+            assert_eq!(evaluate(&board), evaluate(&flipped), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_param_indices() {
+        let params = Param::all();
+        assert_eq!(params.len(), PARAM_COUNT);
+        for (i, param) in params.into_iter().enumerate() {
+            assert_eq!(param.index(), i, "{:?}", param);
+        }
+    }
+}
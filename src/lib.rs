@@ -1,34 +1,170 @@
+pub mod bench;
+pub mod book;
+pub mod chess960;
+pub mod clock;
+pub mod correction_history;
+pub mod datagen;
+pub mod engine;
+pub mod epd;
+pub mod error;
+pub mod ffi;
+pub mod hce;
 pub mod move_ordering;
 pub mod nnue;
+pub mod perft;
+pub mod pgn;
 pub mod position;
+pub mod rng;
+pub mod san;
 pub mod search;
+#[cfg(feature = "trainer")]
+pub mod trainer;
+pub mod tunable;
+pub mod tuner;
 pub mod uci;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub mod wasm;
 
-use std::{fmt, iter::Peekable, str::SplitWhitespace};
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{self, Write},
+    iter::Peekable,
+    str::{FromStr, SplitWhitespace},
+};
 
-use cozy_chess::{Board, Color, File, Move, Piece, Square};
-use position::Position;
+use book::{Book, Selection};
+use cozy_chess::{Color, Move, Square};
+use engine::{Engine, SearchLimits};
+use error::Error;
+use perft::PerftTable;
+use rng::Rng;
+use search::report::UciReporter;
 use uci::Uci;
 
+pub const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 const MAX_DEPTH: u8 = 100;
+const DEFAULT_BOOK_DEPTH: u16 = 20;
+const NO_BOOK: &str = "<empty>";
+const PERFT_HASH_MB: usize = 64;
 
 pub struct Tsunami {
-    pub pos: Position,
+    pub engine: Engine,
+    book: Option<Book>,
+    own_book: bool,
+    /// Book moves are only played up to this move number
+    book_depth: u16,
+    book_selection: Selection,
+    rng: Rng,
+    /// Where UCI output goes, stdout by default
+    out: RefCell<Box<dyn Write + Send>>,
 }
 
 impl Tsunami {
     pub fn new(fen: &str) -> Self {
+        let mut engine = Engine::new();
+        engine.set_position(fen, &[]).unwrap();
         Self {
-            pos: Position::from_fen(fen),
+            engine,
+            book: None,
+            own_book: false,
+            book_depth: DEFAULT_BOOK_DEPTH,
+            book_selection: Selection::Weighted,
+            rng: Rng::from_time(0),
+            out: RefCell::new(Box::new(io::stdout())),
         }
     }
+
+    /// Sends UCI output to `out` instead of stdout
+    pub fn set_output(&mut self, out: impl Write + Send + 'static) {
+        self.out = RefCell::new(Box::new(out));
+    }
+
+    /// Sets up the position of a `position` command
+    fn parse_position(&mut self, args: &mut Peekable<SplitWhitespace>) -> Result<(), Error> {
+        let fen = match args.next() {
+            Some("startpos") => String::from(STARTPOS),
+            Some("fen") => args
+                .by_ref()
+                .take_while(|&arg| arg != "moves")
+                .collect::<Vec<_>>()
+                .join(" "),
+            other => {
+                return Err(Error::InvalidValue {
+                    name: String::from("position"),
+                    value: other.unwrap_or_default().to_string(),
+                })
+            }
+        };
+        let moves: Vec<&str> = args.filter(|&arg| arg != "moves").collect();
+        self.engine.set_position(&fen, &moves)
+    }
+
+    fn try_set_option(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let invalid = || Error::InvalidValue {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        match name {
+            "OwnBook" => self.own_book = value == "true",
+            "BookFile" => {
+                if value.is_empty() || value == NO_BOOK {
+                    self.book = None;
+                    return Ok(());
+                }
+                let book = Book::from_file(value).map_err(|err| Error::Book {
+                    path: value.to_string(),
+                    err,
+                })?;
+                self.send(&format!(
+                    "info string loaded book {} with {} entries",
+                    value,
+                    book.len()
+                ));
+                self.book = Some(book);
+            }
+            "BookDepth" => self.book_depth = value.parse().map_err(|_| invalid())?,
+            "BookSelection" => match value {
+                "Weighted" => self.book_selection = Selection::Weighted,
+                "Best" => self.book_selection = Selection::Best,
+                _ => return Err(invalid()),
+            },
+            _ => match self.engine.set_option(name, value) {
+                Err(Error::UnknownOption(_)) => self.unknown(),
+                Err(err) => return Err(err),
+                Ok(()) if name == "EvalFile" => self.send(&format!(
+                    "info string using network {} {}",
+                    if value.is_empty() {
+                        nnue::EMBEDDED_NAME
+                    } else {
+                        value
+                    },
+                    self.engine.network().arch()
+                )),
+                Ok(()) => {}
+            },
+        }
+        Ok(())
+    }
+
+    fn send_error(&self, err: &Error) {
+        self.send(&format!("info string error {}", err));
+    }
+
+    /// A move from the opening book, if it is enabled and has the position
+    fn book_move(&mut self) -> Option<Move> {
+        if !self.own_book || self.engine.board().fullmove_number() > self.book_depth {
+            return None;
+        }
+        self.book
+            .as_ref()?
+            .probe(self.engine.board(), self.book_selection, &mut self.rng)
+    }
 }
 
 impl Default for Tsunami {
     fn default() -> Self {
-        Self {
-            pos: Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
-        }
+        Self::new(STARTPOS)
     }
 }
 
@@ -40,9 +176,33 @@ impl Uci for Tsunami {
         while let Some(arg) = args.next() {
             match arg {
                 "perft" => {
-                    let depth: u8 = args.next().unwrap().parse().unwrap_or(1);
-                    let now = std::time::Instant::now();
-                    let nodes = crate::perft(&self.pos.board, depth);
+                    let depth: u8 = args.next().and_then(|d| d.parse().ok()).unwrap_or(1);
+                    let mut divide = false;
+                    let mut table = None;
+                    for arg in args.by_ref() {
+                        match arg {
+                            "divide" => divide = true,
+                            "hash" => table = Some(PerftTable::new(PERFT_HASH_MB)),
+                            _ => self.unknown(),
+                        }
+                    }
+                    let now = clock::Instant::now();
+                    let nodes = if divide {
+                        let moves = perft::divide(self.engine.board(), depth, table.as_mut());
+                        for &(mv, nodes) in &moves {
+                            self.send(&format!(
+                                "{}: {}",
+                                chess960::to_uci(self.engine.board(), mv, self.engine.chess960()),
+                                nodes
+                            ));
+                        }
+                        moves.iter().map(|&(_, nodes)| nodes).sum()
+                    } else {
+                        match table.as_mut() {
+                            Some(table) => table.perft(self.engine.board(), depth),
+                            None => perft::perft(self.engine.board(), depth),
+                        }
+                    };
                     let elapsed = now.elapsed().as_secs_f64();
                     self.send(&format!("Nodes: {}", nodes));
                     self.send(&format!(
@@ -51,83 +211,134 @@ impl Uci for Tsunami {
                     ));
                     return;
                 }
-                "wtime" => {
-                    wtime = args.next().unwrap().parse().unwrap();
-                }
-                "btime" => {
-                    btime = args.next().unwrap().parse().unwrap();
-                }
-                "winc" => {
-                    let _winc: u64 = args.next().unwrap().parse().unwrap();
-                }
-                "binc" => {
-                    let _binc: u64 = args.next().unwrap().parse().unwrap();
-                }
-                "movetime" => {
-                    move_time = Some(args.next().unwrap().parse().unwrap());
+                "wtime" => match parse_value(arg, args.next()) {
+                    Ok(value) => wtime = value,
+                    Err(err) => self.send_error(&err),
+                },
+                "btime" => match parse_value(arg, args.next()) {
+                    Ok(value) => btime = value,
+                    Err(err) => self.send_error(&err),
+                },
+                "winc" | "binc" => {
+                    if let Err(err) = parse_value::<u64>(arg, args.next()) {
+                        self.send_error(&err);
+                    }
                 }
+                "movetime" => match parse_value(arg, args.next()) {
+                    Ok(value) => move_time = Some(value),
+                    Err(err) => self.send_error(&err),
+                },
                 _ => {
                     self.unknown();
                 }
             }
         }
 
+        if let Some(mv) = self.book_move() {
+            self.send(&format!(
+                "bestmove {}",
+                chess960::to_uci(self.engine.board(), mv, self.engine.chess960())
+            ));
+            return;
+        }
+
         // Basic time management: use 1% of the time left per move
         // TODO: Better time management
-        let time_left_millis = match self.pos.board.side_to_move() {
+        let time_left_millis = match self.engine.board().side_to_move() {
             Color::White => wtime,
             Color::Black => btime,
         };
+        let limits = SearchLimits {
+            time_millis: move_time.unwrap_or(time_left_millis / 100),
+            ..SearchLimits::default()
+        };
 
-        if let Some(move_time) = move_time {
-            crate::search::think(&mut self.pos, MAX_DEPTH, move_time);
-            return;
-        }
+        let board = self.engine.board().clone();
+        let chess960 = self.engine.chess960();
+        let mut out = self.out.borrow_mut();
+        self.engine
+            .search_with(&limits, &mut UciReporter::new(&board, chess960, &mut *out));
+    }
 
-        crate::search::think(&mut self.pos, MAX_DEPTH, time_left_millis / 100);
+    fn bench(&mut self, depth: u8) {
+        let (nodes, nps) = bench::run(depth);
+        self.send(&format!("{} nodes {} nps", nodes, nps));
     }
 
-    fn display_eval(&mut self) {
-        self.send(&format!("Eval: {}cp", self.pos.eval()));
+    fn display_eval(&mut self, trace: bool) {
+        if trace {
+            self.send(&format!("{}", hce::Trace::new(self.engine.board())));
+        }
+        self.send(&format!("Eval: {}cp", self.engine.eval()));
     }
 
     fn set_position(&mut self, args: &mut Peekable<SplitWhitespace>) {
-        match args.next() {
-            Some("startpos") => {
-                self.pos =
-                    Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-            }
-            Some("fen") => {
-                let mut fen = String::new();
-                for arg in args.by_ref() {
-                    if arg == "moves" {
-                        break;
-                    }
-                    fen.push_str(arg);
-                    fen.push(' ');
-                }
-                self.pos = Position::from_fen(fen.trim_end());
-            }
-            _ => {
-                self.unknown();
-            }
+        // A position that can't be set up leaves the previous one
+        if let Err(err) = self.parse_position(args) {
+            self.send_error(&err);
         }
+    }
 
-        // Play the moves
-        for mv in args.by_ref() {
-            if mv == "moves" {
-                continue;
-            }
+    fn set_option(&mut self, name: &str, value: &str) {
+        if let Err(err) = self.try_set_option(name, value) {
+            self.send_error(&err);
+        }
+    }
 
-            let move_to_play = mv.parse().unwrap();
-            self.pos
-                .make_move(from_uci_castling(&self.pos.board, move_to_play));
+    fn send_options(&self) {
+        self.send(&format!(
+            "option name EvalFile type string default {}",
+            nnue::EMBEDDED_NAME
+        ));
+        self.send("option name Evaluation type combo default NNUE var NNUE var Classical");
+        self.send("option name UCI_Chess960 type check default false");
+        self.send("option name OwnBook type check default false");
+        self.send(&format!(
+            "option name BookFile type string default {}",
+            NO_BOOK
+        ));
+        self.send(&format!(
+            "option name BookDepth type spin default {} min 1 max 500",
+            DEFAULT_BOOK_DEPTH
+        ));
+        self.send(&format!(
+            "option name BookSelection type combo default {} var Weighted var Best",
+            Selection::Weighted.name()
+        ));
+        #[cfg(feature = "tune")]
+        for t in tunable::TUNABLES {
+            self.send(&format!(
+                "option name {} type spin default {} min {} max {}",
+                t.name, t.default, t.min, t.max
+            ));
         }
     }
 
+    fn display_tunables(&self, openbench: bool) {
+        if openbench {
+            self.send(&tunable::openbench_input());
+        } else {
+            self.send(&tunable::weather_factory_json());
+        }
+    }
+
+    fn send(&self, msg: &str) {
+        let mut out = self.out.borrow_mut();
+        let _ = writeln!(out, "{}", msg).and_then(|()| out.flush());
+    }
+
     fn display(&self) {
         self.send(&format!("{}", self));
-        self.send(&format!("FEN: {}", self.pos.board));
+        self.send(&format!(
+            "FEN: {}",
+            chess960::to_fen(self.engine.board(), self.engine.chess960())
+        ));
+
+        let moves: Vec<String> = position::legal_moves(self.engine.board())
+            .into_iter()
+            .map(|mv| san::to_san(self.engine.board(), mv))
+            .collect();
+        self.send(&format!("Legal moves: {}", moves.join(" ")));
     }
 }
 
@@ -139,7 +350,7 @@ impl fmt::Display for Tsunami {
         for rank in (0..8).rev() {
             for file in 0..8 {
                 let sq = Square::index(rank * 8 + file);
-                let pc = self.pos.board.piece_on(sq);
+                let pc = self.engine.board().piece_on(sq);
 
                 // Print the char representation of the piece, or a blank space
                 match pc {
@@ -147,7 +358,7 @@ impl fmt::Display for Tsunami {
                         write!(
                             f,
                             "| {} ",
-                            match self.pos.board.color_on(sq) {
+                            match self.engine.board().color_on(sq) {
                                 Some(color) => match color {
                                     Color::White => format!("{}", pc).to_uppercase(),
                                     Color::Black => format!("{}", pc),
@@ -169,42 +380,11 @@ impl fmt::Display for Tsunami {
     }
 }
 
-/// Count the number of possible positions at a given depth
-/// Implementation from cozy-chess
-fn perft(board: &Board, depth: u8) -> u64 {
-    let mut nodes = 0;
-    match depth {
-        0 => nodes += 1,
-        1 => {
-            board.generate_moves(|moves| {
-                nodes += moves.len() as u64;
-                false
-            });
-        }
-        _ => {
-            board.generate_moves(|moves| {
-                for mv in moves {
-                    let mut board = board.clone();
-                    board.play_unchecked(mv);
-                    let child_nodes = perft(&board, depth - 1);
-                    nodes += child_nodes;
-                }
-                false
-            });
-        }
-    }
-    nodes
-}
-
-/// Convert a move from UCI format to the format cozy-chess uses
-/// Implementation from cozy-chess
-fn from_uci_castling(board: &Board, mut mv: Move) -> Move {
-    if mv.from.file() == File::E && board.piece_on(mv.from) == Some(Piece::King) {
-        if mv.to.file() == File::G {
-            mv.to = Square::new(File::H, mv.to.rank());
-        } else if mv.to.file() == File::C {
-            mv.to = Square::new(File::A, mv.to.rank());
-        }
-    }
-    mv
+/// Parses the value following a command argument
+fn parse_value<T: FromStr>(name: &str, value: Option<&str>) -> Result<T, Error> {
+    let value = value.unwrap_or_default();
+    value.parse().map_err(|_| Error::InvalidValue {
+        name: name.to_string(),
+        value: value.to_string(),
+    })
 }
@@ -1,6 +1,217 @@
-use tsunami::{uci::Uci, Tsunami};
+use tsunami::{
+    bench,
+    book::builder::{self, BuilderOptions},
+    datagen::{self, DataFormat, DatagenOptions},
+    epd::{self, EpdOptions},
+    nnue, perft,
+    tuner::{self, Target, TunerOptions},
+    uci::Uci,
+    Tsunami,
+};
 
 fn main() {
-    let mut tsunami = Tsunami::default();
-    tsunami.uci_loop();
+    let args: Vec<String> = std::env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        Some("convertnet") => {
+            let (Some(input), Some(output)) = (args.get(2), args.get(3)) else {
+                exit_with("Usage: tsunami convertnet <input> <output>");
+            };
+            match nnue::format::convert(input, output) {
+                Ok(arch) => println!("Wrote {} network to {}", arch, output),
+                Err(err) => exit_with(&err.to_string()),
+            }
+        }
+        Some("datagen") => {
+            let options = parse_datagen_options(&args[2..]);
+            if let Err(err) = datagen::run(&options) {
+                exit_with(&format!("Error writing {}: {}", options.out, err));
+            }
+        }
+        #[cfg(feature = "trainer")]
+        Some("train") => {
+            let options = parse_trainer_options(&args[2..]);
+            if let Err(err) = tsunami::trainer::train(&options) {
+                exit_with(&format!("Error training on {}: {}", options.data, err));
+            }
+        }
+        #[cfg(not(feature = "trainer"))]
+        Some("train") => {
+            exit_with("Tsunami was built without the trainer, build it with --features trainer");
+        }
+        Some("makebook") => {
+            let options = parse_builder_options(&args[2..]);
+            if let Err(err) = builder::build(&options) {
+                exit_with(&format!("Error building {}: {}", options.out, err));
+            }
+        }
+        Some("epd") => {
+            let options = parse_epd_options(&args[2..]);
+            if let Err(err) = epd::run(&options) {
+                exit_with(&format!("Error reading test suite: {}", err));
+            }
+        }
+        Some("bench") => {
+            let depth = args
+                .get(2)
+                .map_or(bench::DEFAULT_BENCH_DEPTH, |depth| parse_or_exit(depth));
+            let (nodes, nps) = bench::run(depth);
+            println!("{} nodes {} nps", nodes, nps);
+        }
+        Some("perft-suite") => {
+            let (depth, table) = perft::parse_suite_options(args[2..].iter().map(String::as_str));
+            if !perft::run_suite(depth, table, |line| println!("{}", line)) {
+                std::process::exit(1);
+            }
+        }
+        Some("texel") => {
+            let options = parse_tuner_options(&args[2..]);
+            if let Err(err) = tuner::tune(&options) {
+                exit_with(&format!("Error tuning on {}: {}", options.data, err));
+            }
+        }
+        _ => {
+            let mut tsunami = Tsunami::default();
+            tsunami.uci_loop();
+        }
+    }
+}
+
+/// tsunami datagen [threads <n>] [games <n>] [nodes <n>] [random <plies>] [text] [out <file>]
+fn parse_datagen_options(args: &[String]) -> DatagenOptions {
+    let mut options = DatagenOptions::default();
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| exit_with(&format!("Missing value for {}", arg)))
+        };
+        match arg {
+            "threads" => options.threads = parse_or_exit(value()),
+            "games" => options.games = parse_or_exit(value()),
+            "nodes" => options.nodes = parse_or_exit(value()),
+            "random" => options.random_plies = parse_or_exit(value()),
+            "out" => options.out = value().to_string(),
+            "text" => options.format = DataFormat::Text,
+            "binary" => options.format = DataFormat::Binary,
+            _ => exit_with(&format!("Unknown datagen option {}", arg)),
+        }
+    }
+    options
+}
+
+/// tsunami train <data> [out <file>] [epochs <n>] [batch <n>] [threads <n>] [wdl <x>]
+///     [lr <x>] [lr-step <epochs>] [lr-gamma <x>] [weight-decay <x>]
+#[cfg(feature = "trainer")]
+fn parse_trainer_options(args: &[String]) -> tsunami::trainer::TrainerOptions {
+    let mut options = tsunami::trainer::TrainerOptions::default();
+    let mut args = args.iter().map(String::as_str);
+    match args.next() {
+        Some(data) => options.data = data.to_string(),
+        None => exit_with("Usage: tsunami train <data> [options]"),
+    }
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| exit_with(&format!("Missing value for {}", arg)))
+        };
+        match arg {
+            "out" => options.out = value().to_string(),
+            "epochs" => options.epochs = parse_or_exit(value()),
+            "batch" => options.batch_size = parse_or_exit(value()),
+            "threads" => options.threads = parse_or_exit(value()),
+            "wdl" => options.wdl = parse_or_exit(value()),
+            "lr" => options.lr = parse_or_exit(value()),
+            "lr-step" => options.lr_step = parse_or_exit(value()),
+            "lr-gamma" => options.lr_gamma = parse_or_exit(value()),
+            "weight-decay" => options.weight_decay = parse_or_exit(value()),
+            _ => exit_with(&format!("Unknown train option {}", arg)),
+        }
+    }
+    options
+}
+
+/// tsunami makebook <pgn>... [out <file>] [ply <n>] [min-count <n>] [min-score <percent>]
+fn parse_builder_options(args: &[String]) -> BuilderOptions {
+    let mut options = BuilderOptions::default();
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| exit_with(&format!("Missing value for {}", arg)))
+        };
+        match arg {
+            "out" => options.out = value().to_string(),
+            "ply" => options.max_ply = parse_or_exit(value()),
+            "min-count" => options.min_count = parse_or_exit(value()),
+            "min-score" => options.min_score = parse_or_exit(value()),
+            pgn => options.pgns.push(pgn.to_string()),
+        }
+    }
+    if options.pgns.is_empty() {
+        exit_with("Usage: tsunami makebook <pgn>... [options]");
+    }
+    options
+}
+
+/// tsunami epd <file>... [depth <n>] [nodes <n>] [movetime <ms>]
+fn parse_epd_options(args: &[String]) -> EpdOptions {
+    let mut options = EpdOptions::default();
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| exit_with(&format!("Missing value for {}", arg)))
+        };
+        match arg {
+            "depth" => options.depth = parse_or_exit(value()),
+            "nodes" => options.nodes = parse_or_exit(value()),
+            "movetime" => options.time_millis = parse_or_exit(value()),
+            file => options.files.push(file.to_string()),
+        }
+    }
+    if options.files.is_empty() {
+        exit_with("Usage: tsunami epd <file>... [options]");
+    }
+    options
+}
+
+/// tsunami texel <data> [target classical|scale] [epochs <n>] [lr <x>]
+fn parse_tuner_options(args: &[String]) -> TunerOptions {
+    let mut options = TunerOptions::default();
+    let mut args = args.iter().map(String::as_str);
+    match args.next() {
+        Some(data) => options.data = data.to_string(),
+        None => exit_with("Usage: tsunami texel <data> [options]"),
+    }
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| exit_with(&format!("Missing value for {}", arg)))
+        };
+        match arg {
+            "target" => {
+                options.target = match value() {
+                    "classical" => Target::Classical,
+                    "scale" => Target::Scale,
+                    target => exit_with(&format!("Unknown target {}", target)),
+                }
+            }
+            "epochs" => options.epochs = parse_or_exit(value()),
+            "lr" => options.lr = parse_or_exit(value()),
+            _ => exit_with(&format!("Unknown texel option {}", arg)),
+        }
+    }
+    options
+}
+
+fn parse_or_exit<T: std::str::FromStr>(value: &str) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| exit_with(&format!("Invalid number {}", value)))
+}
+
+fn exit_with(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    std::process::exit(1);
 }
@@ -1,38 +1,192 @@
-const HIDDEN_SIZE: usize = 768;
-const SCALE: i32 = 400;
-const QA: i32 = 181;
-const QB: i32 = 64;
-const QAB: i32 = QA * QB;
+pub mod format;
+pub mod refresh;
+pub mod simd;
 
-pub static NNUE: Network = unsafe { std::mem::transmute(*include_bytes!("../resources/net.bin")) };
+use std::{
+    fmt, fs,
+    path::Path,
+    sync::{Arc, LazyLock},
+};
+
+use cozy_chess::{Board, Color, Piece, Square};
+
+pub use format::Architecture;
+
+/// Size of the hidden layer, every network needs to match it
+pub const HIDDEN_SIZE: usize = 768;
+/// Number of input features per king bucket: 2 colours x 6 pieces x 64 squares
+pub const INPUT_SIZE: usize = 768;
+pub const SCALE: i32 = 400;
+pub const QA: i32 = 181;
+pub const QB: i32 = 64;
+
+/// The network embedded in the binary, shared by every position that doesn't
+/// load another one
+static EMBEDDED: LazyLock<Arc<Network>> = LazyLock::new(|| {
+    Arc::new(
+        Network::from_bytes(include_bytes!("../resources/net.bin"))
+            .expect("the embedded network is valid"),
+    )
+});
+
+/// Name of the embedded network for the `EvalFile` option
+pub const EMBEDDED_NAME: &str = "<embedded>";
+
+/// The network positions evaluate with unless they are given another one
+pub fn embedded_network() -> Arc<Network> {
+    Arc::clone(&EMBEDDED)
+}
+
+/// Reasons a network file can be rejected
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    /// The file is neither a headered network nor a raw one of the right size
+    Size {
+        expected: usize,
+        found: usize,
+    },
+    Checksum {
+        expected: u64,
+        found: u64,
+    },
+    UnsupportedVersion(u16),
+    /// The network was made for an architecture this build can't run
+    Incompatible(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "could not read network: {}", err),
+            LoadError::Size { expected, found } => {
+                write!(f, "network has {} bytes, expected {}", found, expected)
+            }
+            LoadError::Checksum { expected, found } => write!(
+                f,
+                "network checksum is {:016x}, expected {:016x}",
+                found, expected
+            ),
+            LoadError::UnsupportedVersion(version) => write!(
+                f,
+                "network format version {} is not supported, the newest is {}",
+                version,
+                format::VERSION
+            ),
+            LoadError::Incompatible(reason) => write!(f, "incompatible network: {}", reason),
+        }
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(err: std::io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
 
 #[inline]
 /// Activation function
-pub fn screlu(x: i16) -> i32 {
-    i32::from(x.clamp(0, QA as i16)).pow(2)
+pub fn screlu(x: i16, qa: i16) -> i32 {
+    i32::from(x.clamp(0, qa)).pow(2)
 }
 
-#[repr(C)]
+#[derive(Clone, Debug)]
 pub struct Network {
-    /// Column-Major `HIDDEN_SIZE x 768` matrix.
-    feature_weights: [Accumulator; 768],
+    /// Shape and quantisation of the network
+    arch: Architecture,
+    /// Column-Major `HIDDEN_SIZE x INPUT_SIZE` matrix for each king bucket.
+    feature_weights: Vec<Accumulator>,
     /// Vector with dimension `HIDDEN_SIZE`.
     feature_bias: Accumulator,
     /// Column-Major `1 x (2 * HIDDEN_SIZE)`
-    /// matrix, we use it like this to make the
-    /// code nicer in `Network::evaluate`.
-    output_weights: [Accumulator; 2],
-    /// Scalar output bias.
-    output_bias: i16,
+    /// matrix for each output bucket, we use it like this
+    /// to make the code nicer in `Network::evaluate`.
+    output_weights: Vec<[Accumulator; 2]>,
+    /// Scalar output bias of each output bucket.
+    output_bias: Vec<i16>,
 }
 
 impl Network {
+    /// The output of this network for the input features of both sides,
+    /// building the hidden layer from scratch instead of incrementally
+    pub fn evaluate_features(&self, boys: &[usize], opps: &[usize]) -> i32 {
+        let hidden = |features: &[usize]| {
+            let mut acc = self.feature_bias;
+            for &feature in features {
+                simd::update(&mut acc.vals, [&self.feature_weights[feature].vals], []);
+            }
+            acc
+        };
+        self.out(&hidden(boys), &hidden(opps), boys.len())
+    }
+
     /// Calculates the output of the network, starting from the already
     /// calculated hidden layer (done efficiently during makemoves).
-    pub fn out(boys: &Accumulator, opps: &Accumulator) -> i32 {
-        let weights = &NNUE.output_weights;
-        let sum = flatten(boys, &weights[0]) + flatten(opps, &weights[1]);
-        (sum / QA + i32::from(NNUE.output_bias)) * SCALE / QAB
+    /// The output bucket is picked by the number of pieces on the board.
+    #[inline]
+    pub fn out(&self, boys: &Accumulator, opps: &Accumulator, pieces: usize) -> i32 {
+        let arch = &self.arch;
+        let qa = arch.qa as i16;
+        let bucket = output_bucket(pieces, arch.output_buckets);
+        let weights = &self.output_weights[bucket];
+        let sum = flatten(boys, &weights[0], qa) + flatten(opps, &weights[1], qa);
+        (sum / arch.qa + i32::from(self.output_bias[bucket])) * arch.scale / (arch.qa * arch.qb)
+    }
+
+    /// Reads a network from a file, see `Network::from_bytes`
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Network, LoadError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// The shape and quantisation of the network
+    pub fn arch(&self) -> &Architecture {
+        &self.arch
+    }
+}
+
+/// How one side sees the board: the bucket of its king, and whether the
+/// board is mirrored because its king is on the e-h files
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct View {
+    perspective: Color,
+    bucket: usize,
+    mirror: bool,
+}
+
+impl View {
+    pub fn new(net: &Network, board: &Board, perspective: Color) -> Self {
+        let arch = net.arch();
+        let king = relative(board.king(perspective), perspective);
+
+        Self {
+            perspective,
+            bucket: usize::from(arch.bucket_layout[king]),
+            mirror: arch.mirrored && king % 8 >= 4,
+        }
+    }
+
+    pub fn perspective(&self) -> Color {
+        self.perspective
+    }
+
+    /// The index of a piece in the input layer
+    pub fn feature(&self, color: Color, pc: Piece, sq: Square) -> usize {
+        let mut sq = relative(sq, self.perspective);
+        if self.mirror {
+            sq ^= 7;
+        }
+        let side = usize::from(color != self.perspective);
+
+        INPUT_SIZE * self.bucket + 384 * side + 64 * pc as usize + sq
+    }
+}
+
+/// The square seen from the perspective of `color`
+#[inline]
+fn relative(sq: Square, color: Color) -> usize {
+    match color {
+        Color::White => sq as usize,
+        Color::Black => sq as usize ^ 56,
     }
 }
 
@@ -43,31 +197,66 @@ pub struct Accumulator {
 }
 
 impl Accumulator {
-    pub fn update<const ADD: bool>(&mut self, idx: usize) {
-        assert!(idx < 768);
-        for (i, d) in self.vals.iter_mut().zip(&NNUE.feature_weights[idx].vals) {
-            if ADD {
-                *i += *d
-            } else {
-                *i -= *d
-            }
-        }
+    /// The accumulator of an empty board
+    pub fn new(net: &Network) -> Self {
+        net.feature_bias
     }
-}
 
-impl Default for Accumulator {
-    fn default() -> Self {
-        NNUE.feature_bias
+    /// Adds the weights of an input feature
+    pub fn add(&mut self, net: &Network, feature: usize) {
+        let weights = &net.feature_weights;
+        simd::update(&mut self.vals, [&weights[feature].vals], []);
     }
-}
 
-#[inline]
-fn flatten(acc: &Accumulator, weights: &Accumulator) -> i32 {
-    let mut sum = 0;
+    /// Removes the weights of an input feature
+    pub fn sub(&mut self, net: &Network, feature: usize) {
+        let weights = &net.feature_weights;
+        simd::update(&mut self.vals, [], [&weights[feature].vals]);
+    }
+
+    /// Adds one feature and removes another in a single pass, for quiet moves
+    pub fn add_sub(&mut self, net: &Network, add: usize, sub: usize) {
+        let weights = &net.feature_weights;
+        simd::update(&mut self.vals, [&weights[add].vals], [&weights[sub].vals]);
+    }
 
-    for (&x, &w) in acc.vals.iter().zip(&weights.vals) {
-        sum += screlu(x) * i32::from(w);
+    /// Adds one feature and removes two others in a single pass, for captures
+    pub fn add_sub_sub(&mut self, net: &Network, add: usize, sub1: usize, sub2: usize) {
+        let weights = &net.feature_weights;
+        simd::update(
+            &mut self.vals,
+            [&weights[add].vals],
+            [&weights[sub1].vals, &weights[sub2].vals],
+        );
     }
 
-    sum
+    /// Adds two features and removes two others in a single pass, for castling
+    pub fn add_add_sub_sub(
+        &mut self,
+        net: &Network,
+        add1: usize,
+        add2: usize,
+        sub1: usize,
+        sub2: usize,
+    ) {
+        let weights = &net.feature_weights;
+        simd::update(
+            &mut self.vals,
+            [&weights[add1].vals, &weights[add2].vals],
+            [&weights[sub1].vals, &weights[sub2].vals],
+        );
+    }
+}
+
+/// The output bucket for a number of pieces on the board
+/// The buckets split the 2 to 32 pieces of a game evenly
+#[inline]
+pub fn output_bucket(pieces: usize, buckets: usize) -> usize {
+    let divisor = 32usize.div_ceil(buckets);
+    (pieces.saturating_sub(2) / divisor).min(buckets - 1)
+}
+
+#[inline]
+fn flatten(acc: &Accumulator, weights: &Accumulator, qa: i16) -> i32 {
+    simd::screlu_dot(&acc.vals, &weights.vals, qa)
 }
@@ -0,0 +1,453 @@
+//! The file format of networks
+//!
+//! A network file starts with a header, all values little-endian:
+//!
+//! | offset | type      | field                                   |
+//! |--------|-----------|-----------------------------------------|
+//! | 0      | `[u8; 4]` | magic, `TSNN`                           |
+//! | 4      | `u16`     | format version                          |
+//! | 6      | `u16`     | header size, the parameters start here  |
+//! | 8      | `u32`     | input features                          |
+//! | 12     | `u32`     | hidden size                             |
+//! | 16     | `u32`     | output buckets                          |
+//! | 20     | `i32`     | QA                                      |
+//! | 24     | `i32`     | QB                                      |
+//! | 28     | `i32`     | SCALE                                   |
+//! | 32     | `u64`     | FNV-1a hash of the parameters           |
+//! | 40     | `u32`     | input (king) buckets, since version 2   |
+//! | 44     | `u32`     | flags, since version 2                  |
+//! | 64     | `[u8; 64]`| bucket of each king square, version 2   |
+//!
+//! Version 1 headers are 64 bytes, version 2 headers 128 bytes. The rest of
+//! the header is zeroed. It is followed by the `i16` parameters: feature
+//! weights of each input bucket, feature bias, output weights of each output
+//! bucket and the output bias of each output bucket.
+//!
+//! Output buckets are picked by the number of pieces on the board, see
+//! `nnue::output_bucket`. Networks with more than one output bucket are not
+//! scaled by the material on the board.
+//!
+//! The king bucket layout is indexed by the square of the king from the point
+//! of view of the perspective, so a8 is the opponent's back rank for both.
+//!
+//! Files without the magic are raw dumps of the original `#[repr(C)]` network,
+//! like the embedded `net.bin`, optionally followed by an FNV-1a checksum.
+//! They always have the default architecture.
+
+use std::fmt;
+
+use super::{Accumulator, LoadError, Network, HIDDEN_SIZE, INPUT_SIZE, QA, QB, SCALE};
+
+pub const MAGIC: [u8; 4] = *b"TSNN";
+/// The newest format version, the only one written
+pub const VERSION: u16 = 2;
+/// Size of the header of version 1 files
+const V1_HEADER_SIZE: usize = 64;
+const HEADER_SIZE: usize = 128;
+/// The inputs are mirrored horizontally when the king is on files e-h
+const FLAG_MIRRORED: u32 = 1;
+/// A game never has more than 32 pieces to pick output buckets by
+const MAX_OUTPUT_BUCKETS: usize = 32;
+/// Size of the optional checksum appended to a raw network
+const CHECKSUM_SIZE: usize = std::mem::size_of::<u64>();
+
+/// The shape and quantisation of a network
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Architecture {
+    pub input_features: usize,
+    /// Number of king buckets, each with its own feature weights
+    pub input_buckets: usize,
+    /// The king bucket of each (perspective relative) king square
+    pub bucket_layout: [u8; 64],
+    /// Whether the inputs are mirrored when the king is on files e-h
+    pub mirrored: bool,
+    pub hidden_size: usize,
+    pub output_buckets: usize,
+    pub qa: i32,
+    pub qb: i32,
+    pub scale: i32,
+}
+
+impl Default for Architecture {
+    /// The architecture of raw networks
+    fn default() -> Self {
+        Self {
+            input_features: INPUT_SIZE,
+            input_buckets: 1,
+            bucket_layout: [0; 64],
+            mirrored: false,
+            hidden_size: HIDDEN_SIZE,
+            output_buckets: 1,
+            qa: QA,
+            qb: QB,
+            scale: SCALE,
+        }
+    }
+}
+
+impl Architecture {
+    /// Number of `i16` parameters of a network with this architecture
+    fn param_count(&self) -> usize {
+        let hidden = self.hidden_size;
+        self.input_buckets * self.input_features * hidden
+            + hidden
+            + self.output_buckets * (2 * hidden + 1)
+    }
+
+    /// Checks that this build is able to run a network of this architecture
+    fn check_compatible(&self) -> Result<(), LoadError> {
+        let incompatible = |reason: String| Err(LoadError::Incompatible(reason));
+
+        if self.input_features != INPUT_SIZE {
+            return incompatible(format!(
+                "network has {} input features, this build supports {}",
+                self.input_features, INPUT_SIZE
+            ));
+        }
+        if self.input_buckets == 0 {
+            return incompatible(String::from("network has no input buckets"));
+        }
+        if let Some(bucket) = self
+            .bucket_layout
+            .iter()
+            .find(|&&bucket| usize::from(bucket) >= self.input_buckets)
+        {
+            return incompatible(format!(
+                "king bucket layout uses bucket {}, but the network has {}",
+                bucket, self.input_buckets
+            ));
+        }
+        if self.hidden_size != HIDDEN_SIZE {
+            return incompatible(format!(
+                "network has a hidden size of {}, this build supports {}",
+                self.hidden_size, HIDDEN_SIZE
+            ));
+        }
+        if !(1..=MAX_OUTPUT_BUCKETS).contains(&self.output_buckets) {
+            return incompatible(format!(
+                "network has {} output buckets, this build supports 1 to {}",
+                self.output_buckets, MAX_OUTPUT_BUCKETS
+            ));
+        }
+        if self.qa <= 0 || self.qa > i32::from(i16::MAX) || self.qb <= 0 || self.scale <= 0 {
+            return incompatible(format!(
+                "invalid quantisation QA {}, QB {}, SCALE {}",
+                self.qa, self.qb, self.scale
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({}x{}{}->{})x2->1, {} output buckets, QA {}, QB {}, SCALE {}",
+            self.input_features,
+            self.input_buckets,
+            if self.mirrored { "hm" } else { "" },
+            self.hidden_size,
+            self.output_buckets,
+            self.qa,
+            self.qb,
+            self.scale
+        )
+    }
+}
+
+impl Network {
+    /// Reads a network, either with a header or in the raw layout of the
+    /// embedded `net.bin`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, LoadError> {
+        if bytes.starts_with(&MAGIC) {
+            Self::from_headered_bytes(bytes)
+        } else {
+            Self::from_raw_bytes(bytes)
+        }
+    }
+
+    fn from_headered_bytes(bytes: &[u8]) -> Result<Network, LoadError> {
+        if bytes.len() < V1_HEADER_SIZE {
+            return Err(LoadError::Size {
+                expected: V1_HEADER_SIZE,
+                found: bytes.len(),
+            });
+        }
+
+        let u16_at = |i: usize| u16::from_le_bytes(bytes[i..i + 2].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let i32_at = |i: usize| i32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+
+        let version = u16_at(4);
+        let min_header_size = match version {
+            1 => V1_HEADER_SIZE,
+            2 => HEADER_SIZE,
+            _ => return Err(LoadError::UnsupportedVersion(version)),
+        };
+        let header_size = usize::from(u16_at(6)).max(min_header_size);
+        if bytes.len() < header_size {
+            return Err(LoadError::Size {
+                expected: header_size,
+                found: bytes.len(),
+            });
+        }
+
+        // Version 1 networks don't have king buckets
+        let (input_buckets, flags, bucket_layout) = match version {
+            1 => (1, 0, [0; 64]),
+            _ => (
+                u32_at(40) as usize,
+                u32_at(44),
+                bytes[64..128].try_into().unwrap(),
+            ),
+        };
+
+        let arch = Architecture {
+            input_features: u32_at(8) as usize,
+            input_buckets,
+            bucket_layout,
+            mirrored: flags & FLAG_MIRRORED != 0,
+            hidden_size: u32_at(12) as usize,
+            output_buckets: u32_at(16) as usize,
+            qa: i32_at(20),
+            qb: i32_at(24),
+            scale: i32_at(28),
+        };
+        arch.check_compatible()?;
+
+        let expected = header_size + 2 * arch.param_count();
+        if bytes.len() != expected {
+            return Err(LoadError::Size {
+                expected,
+                found: bytes.len(),
+            });
+        }
+
+        let params = &bytes[header_size..];
+        let hash = u64::from_le_bytes(bytes[32..40].try_into().unwrap());
+        let found = fnv1a(params);
+        if hash != found {
+            return Err(LoadError::Checksum {
+                expected: hash,
+                found,
+            });
+        }
+
+        Ok(Self::from_params(arch, params))
+    }
+
+    fn from_raw_bytes(bytes: &[u8]) -> Result<Network, LoadError> {
+        let arch = Architecture::default();
+        let size = raw_size(&arch);
+
+        let params = match bytes.len() {
+            n if n == size => bytes,
+            n if n == size + CHECKSUM_SIZE => {
+                let (params, checksum) = bytes.split_at(size);
+                let expected = u64::from_le_bytes(checksum.try_into().unwrap());
+                let found = fnv1a(params);
+                if expected != found {
+                    return Err(LoadError::Checksum { expected, found });
+                }
+                params
+            }
+            found => {
+                return Err(LoadError::Size {
+                    expected: size,
+                    found,
+                })
+            }
+        };
+
+        Ok(Self::from_params(arch, params))
+    }
+
+    /// Builds a network from its little-endian parameters
+    /// `params` has to hold at least `arch.param_count()` of them
+    fn from_params(arch: Architecture, params: &[u8]) -> Network {
+        let mut vals = params
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]));
+
+        let feature_weights = (0..arch.input_buckets * arch.input_features)
+            .map(|_| read_accumulator(&mut vals))
+            .collect();
+        let feature_bias = read_accumulator(&mut vals);
+        let output_weights = (0..arch.output_buckets)
+            .map(|_| [read_accumulator(&mut vals), read_accumulator(&mut vals)])
+            .collect();
+        let output_bias = vals.by_ref().take(arch.output_buckets).collect();
+
+        Network {
+            arch,
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias,
+        }
+    }
+
+    /// Builds a network from its parameters, in the same order as in a file
+    pub fn from_parameters(arch: Architecture, params: &[i16]) -> Result<Network, LoadError> {
+        arch.check_compatible()?;
+        if params.len() != arch.param_count() {
+            return Err(LoadError::Size {
+                expected: 2 * arch.param_count(),
+                found: 2 * params.len(),
+            });
+        }
+
+        let bytes: Vec<u8> = params.iter().flat_map(|p| p.to_le_bytes()).collect();
+        Ok(Self::from_params(arch, &bytes))
+    }
+
+    /// The network in the current file format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let params = self.params();
+
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + params.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        bytes.extend_from_slice(&(self.arch.input_features as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.arch.hidden_size as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.arch.output_buckets as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.arch.qa.to_le_bytes());
+        bytes.extend_from_slice(&self.arch.qb.to_le_bytes());
+        bytes.extend_from_slice(&self.arch.scale.to_le_bytes());
+        bytes.extend_from_slice(&fnv1a(&params).to_le_bytes());
+        bytes.extend_from_slice(&(self.arch.input_buckets as u32).to_le_bytes());
+        let flags = if self.arch.mirrored { FLAG_MIRRORED } else { 0 };
+        bytes.extend_from_slice(&flags.to_le_bytes());
+        bytes.resize(V1_HEADER_SIZE, 0);
+        bytes.extend_from_slice(&self.arch.bucket_layout);
+
+        bytes.extend_from_slice(&params);
+        bytes
+    }
+
+    /// The network in the raw layout of `net.bin`
+    /// Returns `None` if the network doesn't have the default architecture
+    pub fn to_raw_bytes(&self) -> Option<Vec<u8>> {
+        if self.arch != Architecture::default() {
+            return None;
+        }
+
+        let mut bytes = self.params();
+        bytes.resize(raw_size(&self.arch), 0);
+        Some(bytes)
+    }
+
+    /// The little-endian parameters of the network
+    fn params(&self) -> Vec<u8> {
+        let mut params = Vec::with_capacity(2 * self.arch.param_count());
+        let accumulators = self
+            .feature_weights
+            .iter()
+            .chain([&self.feature_bias])
+            .chain(self.output_weights.iter().flatten());
+        for acc in accumulators {
+            for val in acc.vals {
+                params.extend_from_slice(&val.to_le_bytes());
+            }
+        }
+        for bias in &self.output_bias {
+            params.extend_from_slice(&bias.to_le_bytes());
+        }
+        params
+    }
+}
+
+/// Reads the next `HIDDEN_SIZE` values into an accumulator
+fn read_accumulator(vals: &mut impl Iterator<Item = i16>) -> Accumulator {
+    let mut acc = Accumulator {
+        vals: [0; HIDDEN_SIZE],
+    };
+    for (val, read) in acc.vals.iter_mut().zip(vals) {
+        *val = read;
+    }
+    acc
+}
+
+/// Size of a raw network, `#[repr(C)]` padded the network to the 64 byte
+/// alignment of the accumulators
+fn raw_size(arch: &Architecture) -> usize {
+    (2 * arch.param_count()).next_multiple_of(64)
+}
+
+/// 64-bit FNV-1a hash, used as the checksum of network files
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Wraps a network in the current file format, for example to convert a raw
+/// `net.bin`
+pub fn convert(input: &str, output: &str) -> Result<Architecture, LoadError> {
+    let net = Network::from_file(input)?;
+    std::fs::write(output, net.to_bytes())?;
+    Ok(net.arch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NET: &[u8] = include_bytes!("../../resources/net.bin");
+
+    #[test]
+    // Networks are accepted with or without a header, but only if they are intact
+    fn test_load_network() {
+        let net = Network::from_bytes(NET).unwrap();
+        assert_eq!(net.to_raw_bytes().unwrap(), NET);
+
+        let mut with_checksum = NET.to_vec();
+        with_checksum.extend_from_slice(&fnv1a(NET).to_le_bytes());
+        assert!(Network::from_bytes(&with_checksum).is_ok());
+        with_checksum[0] ^= 1;
+        assert!(matches!(
+            Network::from_bytes(&with_checksum),
+            Err(LoadError::Checksum { .. })
+        ));
+        assert!(matches!(
+            Network::from_bytes(&NET[1..]),
+            Err(LoadError::Size { .. })
+        ));
+
+        let headered = net.to_bytes();
+        let reloaded = Network::from_bytes(&headered).unwrap();
+        assert_eq!(reloaded.to_raw_bytes().unwrap(), NET);
+
+        let mut corrupted = headered.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            Network::from_bytes(&corrupted),
+            Err(LoadError::Checksum { .. })
+        ));
+
+        let mut wrong_size = headered.clone();
+        wrong_size[12..16].copy_from_slice(&1024u32.to_le_bytes());
+        assert!(matches!(
+            Network::from_bytes(&wrong_size),
+            Err(LoadError::Incompatible(_))
+        ));
+
+        // Version 1 files have a shorter header without king buckets
+        let mut v1 = headered[..V1_HEADER_SIZE].to_vec();
+        v1[4..6].copy_from_slice(&1u16.to_le_bytes());
+        v1[6..8].copy_from_slice(&(V1_HEADER_SIZE as u16).to_le_bytes());
+        v1[40..].fill(0);
+        v1.extend_from_slice(&headered[HEADER_SIZE..]);
+        let reloaded = Network::from_bytes(&v1).unwrap();
+        assert_eq!(reloaded.to_raw_bytes().unwrap(), NET);
+
+        let mut newer = headered;
+        newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Network::from_bytes(&newer),
+            Err(LoadError::UnsupportedVersion(_))
+        ));
+    }
+}
@@ -0,0 +1,82 @@
+//! Accumulator refreshes when a king changes bucket
+//!
+//! Rebuilding an accumulator from scratch adds the weights of every piece.
+//! Instead, each thread remembers the accumulator it last built for every
+//! king bucket along with the board it was built for (a "Finny table"), and
+//! only applies the difference between that board and the current one.
+
+use std::{
+    cell::RefCell,
+    sync::{Arc, Weak},
+};
+
+use cozy_chess::{BitBoard, Board, Color, Piece};
+
+use super::{Accumulator, Network, View};
+
+#[derive(Clone)]
+struct Entry {
+    acc: Accumulator,
+    colors: [BitBoard; 2],
+    pieces: [BitBoard; 6],
+}
+
+struct RefreshTable {
+    /// The network the entries were built with. Holding on to it keeps its
+    /// address from being reused by another network once it is dropped
+    network: Weak<Network>,
+    /// Indexed by perspective, king bucket and mirroring
+    entries: Vec<Entry>,
+}
+
+thread_local! {
+    static TABLE: RefCell<RefreshTable> = const {
+        RefCell::new(RefreshTable {
+            network: Weak::new(),
+            entries: Vec::new(),
+        })
+    };
+}
+
+/// Sets `acc` to the accumulator of the board seen through `view`
+pub fn refresh(net: &Arc<Network>, acc: &mut Accumulator, board: &Board, view: View) {
+    TABLE.with_borrow_mut(|table| {
+        // Entries built with another network are useless
+        if !std::ptr::eq(table.network.as_ptr(), Arc::as_ptr(net)) {
+            let empty = Entry {
+                acc: net.feature_bias,
+                colors: [BitBoard::EMPTY; 2],
+                pieces: [BitBoard::EMPTY; 6],
+            };
+            table.network = Arc::downgrade(net);
+            table.entries = vec![empty; 2 * 2 * net.arch().input_buckets];
+        }
+
+        let index = (view.perspective as usize * net.arch().input_buckets + view.bucket) * 2
+            + usize::from(view.mirror);
+        let entry = &mut table.entries[index];
+
+        for color in Color::ALL {
+            for pc in Piece::ALL {
+                let old = entry.colors[color as usize] & entry.pieces[pc as usize];
+                let new = board.colored_pieces(color, pc);
+
+                for sq in new & !old {
+                    entry.acc.add(net, view.feature(color, pc, sq));
+                }
+                for sq in old & !new {
+                    entry.acc.sub(net, view.feature(color, pc, sq));
+                }
+            }
+        }
+
+        for color in Color::ALL {
+            entry.colors[color as usize] = board.colors(color);
+        }
+        for pc in Piece::ALL {
+            entry.pieces[pc as usize] = board.pieces(pc);
+        }
+
+        *acc = entry.acc;
+    });
+}
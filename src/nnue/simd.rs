@@ -0,0 +1,353 @@
+//! Vectorised accumulator updates and output layer
+//!
+//! On x86_64 the fastest of AVX-512, AVX2 and SSE4.1 is picked at runtime,
+//! everywhere else the scalar versions are used. Every path gives exactly the
+//! same results as the scalar one.
+
+use std::sync::LazyLock;
+
+use super::HIDDEN_SIZE;
+
+type Vals = [i16; HIDDEN_SIZE];
+
+// Every vector width has to divide the hidden size
+const _: () = assert!(HIDDEN_SIZE.is_multiple_of(32));
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Scalar,
+    #[cfg(target_arch = "x86_64")]
+    Sse41,
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+    #[cfg(target_arch = "x86_64")]
+    Avx512,
+}
+
+/// The best instruction set supported by this CPU
+static LEVEL: LazyLock<Level> = LazyLock::new(|| {
+    *available_levels()
+        .last()
+        .expect("scalar is always available")
+});
+
+/// Every instruction set supported by this CPU, from slowest to fastest
+pub fn available_levels() -> Vec<Level> {
+    #[allow(unused_mut)]
+    let mut levels = vec![Level::Scalar];
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("sse4.1") {
+            levels.push(Level::Sse41);
+        }
+        if is_x86_feature_detected!("avx2") {
+            levels.push(Level::Avx2);
+        }
+        if is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512bw") {
+            levels.push(Level::Avx512);
+        }
+    }
+
+    levels
+}
+
+/// The instruction set used for evaluation
+pub fn level() -> Level {
+    *LEVEL
+}
+
+/// Adds every vector of `adds` to `acc` and subtracts every vector of `subs`
+#[inline]
+pub fn update<const ADDS: usize, const SUBS: usize>(
+    acc: &mut Vals,
+    adds: [&Vals; ADDS],
+    subs: [&Vals; SUBS],
+) {
+    update_with(level(), acc, adds, subs);
+}
+
+/// The SCReLU activated accumulator dotted with the output weights
+#[inline]
+pub fn screlu_dot(acc: &Vals, weights: &Vals, qa: i16) -> i32 {
+    screlu_dot_with(level(), acc, weights, qa)
+}
+
+#[inline]
+fn update_with<const ADDS: usize, const SUBS: usize>(
+    level: Level,
+    acc: &mut Vals,
+    adds: [&Vals; ADDS],
+    subs: [&Vals; SUBS],
+) {
+    // SAFETY: the level is only available if the CPU supports it
+    match level {
+        Level::Scalar => scalar::update(acc, adds, subs),
+        #[cfg(target_arch = "x86_64")]
+        Level::Sse41 => unsafe { sse41::update(acc, adds, subs) },
+        #[cfg(target_arch = "x86_64")]
+        Level::Avx2 => unsafe { avx2::update(acc, adds, subs) },
+        #[cfg(target_arch = "x86_64")]
+        Level::Avx512 => unsafe { avx512::update(acc, adds, subs) },
+    }
+}
+
+#[inline]
+fn screlu_dot_with(level: Level, acc: &Vals, weights: &Vals, qa: i16) -> i32 {
+    // SAFETY: the level is only available if the CPU supports it
+    match level {
+        Level::Scalar => scalar::screlu_dot(acc, weights, qa),
+        #[cfg(target_arch = "x86_64")]
+        Level::Sse41 => unsafe { sse41::screlu_dot(acc, weights, qa) },
+        #[cfg(target_arch = "x86_64")]
+        Level::Avx2 => unsafe { avx2::screlu_dot(acc, weights, qa) },
+        #[cfg(target_arch = "x86_64")]
+        Level::Avx512 => unsafe { avx512::screlu_dot(acc, weights, qa) },
+    }
+}
+
+mod scalar {
+    use super::Vals;
+
+    pub fn update<const ADDS: usize, const SUBS: usize>(
+        acc: &mut Vals,
+        adds: [&Vals; ADDS],
+        subs: [&Vals; SUBS],
+    ) {
+        for (i, val) in acc.iter_mut().enumerate() {
+            for add in adds {
+                *val = val.wrapping_add(add[i]);
+            }
+            for sub in subs {
+                *val = val.wrapping_sub(sub[i]);
+            }
+        }
+    }
+
+    pub fn screlu_dot(acc: &Vals, weights: &Vals, qa: i16) -> i32 {
+        acc.iter().zip(weights).fold(0i32, |sum, (&x, &w)| {
+            sum.wrapping_add(crate::nnue::screlu(x, qa).wrapping_mul(i32::from(w)))
+        })
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse41 {
+    use std::arch::x86_64::*;
+
+    use super::{Vals, HIDDEN_SIZE};
+
+    const LANES: usize = 8;
+
+    #[target_feature(enable = "sse4.1")]
+    pub unsafe fn update<const ADDS: usize, const SUBS: usize>(
+        acc: &mut Vals,
+        adds: [&Vals; ADDS],
+        subs: [&Vals; SUBS],
+    ) {
+        for i in (0..HIDDEN_SIZE).step_by(LANES) {
+            let ptr = acc.as_mut_ptr().add(i) as *mut __m128i;
+            let mut v = _mm_loadu_si128(ptr);
+            for add in adds {
+                v = _mm_add_epi16(v, _mm_loadu_si128(add.as_ptr().add(i) as *const __m128i));
+            }
+            for sub in subs {
+                v = _mm_sub_epi16(v, _mm_loadu_si128(sub.as_ptr().add(i) as *const __m128i));
+            }
+            _mm_storeu_si128(ptr, v);
+        }
+    }
+
+    #[target_feature(enable = "sse4.1")]
+    pub unsafe fn screlu_dot(acc: &Vals, weights: &Vals, qa: i16) -> i32 {
+        let zero = _mm_setzero_si128();
+        let max = _mm_set1_epi16(qa);
+        let mut sum = _mm_setzero_si128();
+
+        for i in (0..HIDDEN_SIZE).step_by(LANES) {
+            let x = _mm_loadu_si128(acc.as_ptr().add(i) as *const __m128i);
+            let w = _mm_loadu_si128(weights.as_ptr().add(i) as *const __m128i);
+            let x = _mm_min_epi16(_mm_max_epi16(x, zero), max);
+
+            // Widen to 32 bits, the products don't fit in 16
+            for (x, w) in [(x, w), (_mm_srli_si128(x, 8), _mm_srli_si128(w, 8))] {
+                let x = _mm_cvtepi16_epi32(x);
+                let w = _mm_cvtepi16_epi32(w);
+                sum = _mm_add_epi32(sum, _mm_mullo_epi32(_mm_mullo_epi32(x, x), w));
+            }
+        }
+
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b01_00_11_10));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b10_11_00_01));
+        _mm_cvtsi128_si32(sum)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::{Vals, HIDDEN_SIZE};
+
+    const LANES: usize = 16;
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn update<const ADDS: usize, const SUBS: usize>(
+        acc: &mut Vals,
+        adds: [&Vals; ADDS],
+        subs: [&Vals; SUBS],
+    ) {
+        for i in (0..HIDDEN_SIZE).step_by(LANES) {
+            let ptr = acc.as_mut_ptr().add(i) as *mut __m256i;
+            let mut v = _mm256_loadu_si256(ptr);
+            for add in adds {
+                v = _mm256_add_epi16(v, _mm256_loadu_si256(add.as_ptr().add(i) as *const __m256i));
+            }
+            for sub in subs {
+                v = _mm256_sub_epi16(v, _mm256_loadu_si256(sub.as_ptr().add(i) as *const __m256i));
+            }
+            _mm256_storeu_si256(ptr, v);
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn screlu_dot(acc: &Vals, weights: &Vals, qa: i16) -> i32 {
+        let zero = _mm256_setzero_si256();
+        let max = _mm256_set1_epi16(qa);
+        let mut sum = _mm256_setzero_si256();
+
+        for i in (0..HIDDEN_SIZE).step_by(LANES) {
+            let x = _mm256_loadu_si256(acc.as_ptr().add(i) as *const __m256i);
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+            let x = _mm256_min_epi16(_mm256_max_epi16(x, zero), max);
+
+            // Widen to 32 bits, the products don't fit in 16
+            let halves = [
+                (_mm256_castsi256_si128(x), _mm256_castsi256_si128(w)),
+                (
+                    _mm256_extracti128_si256(x, 1),
+                    _mm256_extracti128_si256(w, 1),
+                ),
+            ];
+            for (x, w) in halves {
+                let x = _mm256_cvtepi16_epi32(x);
+                let w = _mm256_cvtepi16_epi32(w);
+                sum = _mm256_add_epi32(sum, _mm256_mullo_epi32(_mm256_mullo_epi32(x, x), w));
+            }
+        }
+
+        let sum = _mm_add_epi32(
+            _mm256_castsi256_si128(sum),
+            _mm256_extracti128_si256(sum, 1),
+        );
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b01_00_11_10));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b10_11_00_01));
+        _mm_cvtsi128_si32(sum)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx512 {
+    use std::arch::x86_64::*;
+
+    use super::{Vals, HIDDEN_SIZE};
+
+    const LANES: usize = 32;
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn update<const ADDS: usize, const SUBS: usize>(
+        acc: &mut Vals,
+        adds: [&Vals; ADDS],
+        subs: [&Vals; SUBS],
+    ) {
+        for i in (0..HIDDEN_SIZE).step_by(LANES) {
+            let ptr = acc.as_mut_ptr().add(i) as *mut __m512i;
+            let mut v = _mm512_loadu_si512(ptr);
+            for add in adds {
+                v = _mm512_add_epi16(v, _mm512_loadu_si512(add.as_ptr().add(i) as *const __m512i));
+            }
+            for sub in subs {
+                v = _mm512_sub_epi16(v, _mm512_loadu_si512(sub.as_ptr().add(i) as *const __m512i));
+            }
+            _mm512_storeu_si512(ptr, v);
+        }
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn screlu_dot(acc: &Vals, weights: &Vals, qa: i16) -> i32 {
+        let zero = _mm512_setzero_si512();
+        let max = _mm512_set1_epi16(qa);
+        let mut sum = _mm512_setzero_si512();
+
+        for i in (0..HIDDEN_SIZE).step_by(LANES) {
+            let x = _mm512_loadu_si512(acc.as_ptr().add(i) as *const __m512i);
+            let w = _mm512_loadu_si512(weights.as_ptr().add(i) as *const __m512i);
+            let x = _mm512_min_epi16(_mm512_max_epi16(x, zero), max);
+
+            // Widen to 32 bits, the products don't fit in 16
+            let halves = [
+                (_mm512_castsi512_si256(x), _mm512_castsi512_si256(w)),
+                (
+                    _mm512_extracti64x4_epi64(x, 1),
+                    _mm512_extracti64x4_epi64(w, 1),
+                ),
+            ];
+            for (x, w) in halves {
+                let x = _mm512_cvtepi16_epi32(x);
+                let w = _mm512_cvtepi16_epi32(w);
+                sum = _mm512_add_epi32(sum, _mm512_mullo_epi32(_mm512_mullo_epi32(x, x), w));
+            }
+        }
+
+        _mm512_reduce_add_epi32(sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random values covering the whole `i16` range
+    fn random_vals(seed: &mut u64) -> Vals {
+        let mut vals = [0; HIDDEN_SIZE];
+        for val in vals.iter_mut() {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            *val = *seed as i16;
+        }
+        vals
+    }
+
+    #[test]
+    // Every vectorised path has to give bit-identical outputs to the scalar one
+    fn test_simd_matches_scalar() {
+        let mut seed = 0x1234_5678_9abc_def1;
+        for _ in 0..64 {
+            let acc = random_vals(&mut seed);
+            let a = random_vals(&mut seed);
+            let b = random_vals(&mut seed);
+            // Small weights, like real output weights
+            let weights = random_vals(&mut seed).map(|w| w >> 8);
+            // Activations both inside and outside of the clipping range
+            let clipped = acc.map(|x| x >> 7);
+
+            let mut expected = [acc; 3];
+            update_with(Level::Scalar, &mut expected[0], [&a], []);
+            update_with(Level::Scalar, &mut expected[1], [&a], [&b]);
+            update_with(Level::Scalar, &mut expected[2], [&a], [&b, &weights]);
+            let expected_dot = screlu_dot_with(Level::Scalar, &clipped, &weights, 181);
+
+            for level in available_levels() {
+                let mut found = [acc; 3];
+                update_with(level, &mut found[0], [&a], []);
+                update_with(level, &mut found[1], [&a], [&b]);
+                update_with(level, &mut found[2], [&a], [&b, &weights]);
+                assert_eq!(found, expected, "{:?} updates differ", level);
+
+                let dot = screlu_dot_with(level, &clipped, &weights, 181);
+                assert_eq!(dot, expected_dot, "{:?} output differs", level);
+            }
+        }
+    }
+}
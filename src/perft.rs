@@ -0,0 +1,232 @@
+//! Move generation tests
+//!
+//! Perft counts the leaf nodes of the legal move tree to a fixed depth, which
+//! can be compared against known counts to find move generation bugs.
+
+use cozy_chess::{Board, Move};
+
+use crate::{chess960, clock::Instant};
+
+const SUITE_HASH_MB: usize = 64;
+
+pub fn perft(board: &Board, depth: u8) -> u64 {
+    let mut nodes = 0;
+    match depth {
+        0 => nodes += 1,
+        1 => {
+            board.generate_moves(|moves| {
+                nodes += moves.len() as u64;
+                false
+            });
+        }
+        _ => {
+            board.generate_moves(|moves| {
+                for mv in moves {
+                    let mut board = board.clone();
+                    board.play_unchecked(mv);
+                    let child_nodes = perft(&board, depth - 1);
+                    nodes += child_nodes;
+                }
+                false
+            });
+        }
+    }
+    nodes
+}
+
+/// The perft of every legal move
+pub fn divide(board: &Board, depth: u8, table: Option<&mut PerftTable>) -> Vec<(Move, u64)> {
+    let mut moves = Vec::new();
+    board.generate_moves(|mvs| {
+        moves.extend(mvs);
+        false
+    });
+    let mut table = table;
+    moves
+        .into_iter()
+        .map(|mv| {
+            let mut child = board.clone();
+            child.play_unchecked(mv);
+            let depth = depth.saturating_sub(1);
+            let nodes = match table.as_deref_mut() {
+                Some(table) => table.perft(&child, depth),
+                None => perft(&child, depth),
+            };
+            (mv, nodes)
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Default)]
+struct Entry {
+    hash: u64,
+    depth: u8,
+    nodes: u64,
+}
+
+/// Perft with the counts of transpositions stored in a hash table
+pub struct PerftTable {
+    entries: Vec<Entry>,
+}
+
+impl PerftTable {
+    pub fn new(mb: usize) -> Self {
+        let len = (mb * 1024 * 1024 / size_of::<Entry>()).max(1);
+        Self {
+            entries: vec![Entry::default(); len],
+        }
+    }
+
+    pub fn perft(&mut self, board: &Board, depth: u8) -> u64 {
+        if depth <= 1 {
+            return perft(board, depth);
+        }
+        let index = (board.hash() % self.entries.len() as u64) as usize;
+        let entry = self.entries[index];
+        if entry.hash == board.hash() && entry.depth == depth {
+            return entry.nodes;
+        }
+
+        let mut nodes = 0;
+        board.generate_moves(|moves| {
+            for mv in moves {
+                let mut board = board.clone();
+                board.play_unchecked(mv);
+                nodes += self.perft(&board, depth - 1);
+            }
+            false
+        });
+        self.entries[index] = Entry {
+            hash: board.hash(),
+            depth,
+            nodes,
+        };
+        nodes
+    }
+}
+
+/// Positions with known perft counts, from depth 1
+/// Castling rights of Chess960 positions are in X-FEN or Shredder-FEN
+pub const SUITE: [(&str, &[u64]); 13] = [
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        &[20, 400, 8902, 197281, 4865609],
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        &[48, 2039, 97862, 4085603],
+    ),
+    (
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        &[14, 191, 2812, 43238, 674624],
+    ),
+    (
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        &[6, 264, 9467, 422333],
+    ),
+    (
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        &[44, 1486, 62379, 2103487],
+    ),
+    (
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        &[46, 2079, 89890, 3894594],
+    ),
+    (
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        &[21, 528, 12189, 326672, 8146062],
+    ),
+    (
+        "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+        &[21, 807, 18002, 667366, 16253601],
+    ),
+    (
+        "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+        &[20, 479, 10471, 273318, 6417013],
+    ),
+    (
+        "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+        &[22, 593, 13440, 382958, 9183776],
+    ),
+    (
+        "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+        &[28, 1120, 31058, 1171749, 34030312],
+    ),
+    (
+        "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1",
+        &[20, 400, 9006, 201143, 4975808],
+    ),
+    (
+        "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w CAca - 0 1",
+        &[20, 400, 9006, 201143, 4973573],
+    ),
+];
+
+/// `[depth] [hash]`, checking every known depth and without a hash table by
+/// default
+pub fn parse_suite_options<'a>(args: impl Iterator<Item = &'a str>) -> (u8, Option<PerftTable>) {
+    let mut depth = u8::MAX;
+    let mut table = None;
+    for arg in args {
+        match arg {
+            "hash" => table = Some(PerftTable::new(SUITE_HASH_MB)),
+            _ => depth = arg.parse().unwrap_or(depth),
+        }
+    }
+    (depth, table)
+}
+
+/// Checks the suite up to `max_depth`, reporting the results line by line
+/// Returns whether every count matched
+pub fn run_suite(
+    max_depth: u8,
+    mut table: Option<PerftTable>,
+    mut report: impl FnMut(&str),
+) -> bool {
+    let mut passed = true;
+    let start_time = Instant::now();
+    for (fen, counts) in SUITE {
+        let board = chess960::parse_fen(fen).unwrap();
+        let mut ok = true;
+        for (depth, &expected) in (1..=max_depth).zip(counts) {
+            let nodes = match table.as_mut() {
+                Some(table) => table.perft(&board, depth),
+                None => perft(&board, depth),
+            };
+            if nodes != expected {
+                report(&format!(
+                    "FAIL {} depth {}: {} nodes, expected {}",
+                    fen, depth, nodes, expected
+                ));
+                ok = false;
+            }
+        }
+        report(&format!("{} {}", if ok { "ok  " } else { "FAIL" }, fen));
+        passed &= ok;
+    }
+    report(&format!(
+        "{} in {:.2}s",
+        if passed { "Passed" } else { "Failed" },
+        start_time.elapsed().as_secs_f64()
+    ));
+    passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perft_suite() {
+        let mut table = PerftTable::new(1);
+        for (fen, counts) in SUITE {
+            let board = chess960::parse_fen(fen).unwrap();
+            for (depth, &expected) in (1..=3).zip(counts) {
+                assert_eq!(perft(&board, depth), expected, "{}", fen);
+                assert_eq!(table.perft(&board, depth), expected, "{}", fen);
+            }
+            let total: u64 = divide(&board, 3, None).iter().map(|&(_, n)| n).sum();
+            assert_eq!(total, counts[2]);
+        }
+    }
+}
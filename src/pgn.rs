@@ -0,0 +1,535 @@
+//! Reading and writing PGN games
+//!
+//! Games are read into a tree: the main line is a list of nodes, and every
+//! node holds the variations that were given as alternatives to its move.
+//! Every move is checked against the position it is played in, and games are
+//! written back with SAN moves.
+
+use std::fmt;
+
+use cozy_chess::{Board, Color, Move};
+
+use crate::{
+    chess960,
+    san::{parse_san, to_san},
+};
+
+/// Lines of written movetext are wrapped at this width
+const LINE_WIDTH: usize = 80;
+/// The tags every game has, in the order they are written
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PgnError {
+    /// A move that is illegal or ambiguous in its position
+    IllegalMove(String),
+    InvalidFen(String),
+    /// A token that doesn't fit where it was found
+    Syntax(String),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::IllegalMove(san) => write!(f, "illegal move {}", san),
+            PgnError::InvalidFen(fen) => write!(f, "invalid FEN {}", fen),
+            PgnError::Syntax(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// A move of a game and everything attached to it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub mv: Move,
+    /// Numeric annotation glyphs, `!` is 1, `?` is 2 and so on
+    pub nags: Vec<u8>,
+    /// A comment before the move, only written at the start of a variation
+    pub starting_comment: Option<String>,
+    pub comment: Option<String>,
+    /// Lines played instead of this move
+    pub variations: Vec<Vec<Node>>,
+}
+
+impl Node {
+    pub fn new(mv: Move) -> Self {
+        Self {
+            mv,
+            nags: Vec::new(),
+            starting_comment: None,
+            comment: None,
+            variations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    /// In the order they were read
+    pub tags: Vec<(String, String)>,
+    pub start: Board,
+    /// A comment before the first move
+    pub comment: Option<String>,
+    pub moves: Vec<Node>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new(Board::default())
+    }
+}
+
+impl Game {
+    /// An empty game with the seven tag roster, from `start`
+    pub fn new(start: Board) -> Self {
+        let mut game = Self {
+            tags: SEVEN_TAG_ROSTER
+                .iter()
+                .map(|&name| {
+                    (
+                        name.to_string(),
+                        String::from(if name == "Result" { "*" } else { "?" }),
+                    )
+                })
+                .collect(),
+            start,
+            comment: None,
+            moves: Vec::new(),
+        };
+        if game.start != Board::default() {
+            game.set_tag("SetUp", "1");
+            let fen = game.start.to_string();
+            game.set_tag("FEN", &fen);
+        }
+        game
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// `1-0`, `0-1`, `1/2-1/2` or `*`
+    pub fn result(&self) -> &str {
+        self.tag("Result").unwrap_or("*")
+    }
+
+    /// The result from white's point of view, if the game is finished
+    pub fn white_score(&self) -> Option<f32> {
+        match self.result() {
+            "1-0" => Some(1.0),
+            "0-1" => Some(0.0),
+            "1/2-1/2" => Some(0.5),
+            _ => None,
+        }
+    }
+
+    /// Plays a move at the end of the main line, it must be legal
+    pub fn push(&mut self, mv: Move) {
+        self.moves.push(Node::new(mv));
+    }
+
+    pub fn mainline(&self) -> impl Iterator<Item = Move> + '_ {
+        self.moves.iter().map(|node| node.mv)
+    }
+
+    /// The position at the end of the main line
+    pub fn board(&self) -> Board {
+        let mut board = self.start.clone();
+        for mv in self.mainline() {
+            board.play_unchecked(mv);
+        }
+        board
+    }
+}
+
+/// Reads every game of a PGN file
+pub fn parse(pgn: &str) -> Result<Vec<Game>, PgnError> {
+    Reader::new(pgn).collect()
+}
+
+/// Reads the games of a PGN file one at a time
+/// After a game with an error, reading carries on with the next game
+pub struct Reader<'a> {
+    tokens: Vec<Token<'a>>,
+    index: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(pgn: &'a str) -> Self {
+        Self {
+            tokens: tokenize(pgn),
+            index: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.index)
+    }
+
+    fn game(&mut self) -> Result<Game, PgnError> {
+        let mut tags = Vec::new();
+        while let Some(Token::Tag(name, value)) = self.peek() {
+            tags.push((name.to_string(), value.clone()));
+            self.index += 1;
+        }
+
+        let start = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => {
+                chess960::parse_fen(fen).map_err(|_| PgnError::InvalidFen(fen.clone()))?
+            }
+            None => Board::default(),
+        };
+        let mut game = Game {
+            tags,
+            start: start.clone(),
+            comment: None,
+            moves: Vec::new(),
+        };
+
+        let mut pending_comment = None;
+        game.moves = self.line(start, 0, &mut pending_comment)?;
+        game.comment = match game.moves.first_mut() {
+            Some(first) => first.starting_comment.take(),
+            None => pending_comment,
+        };
+
+        if let Some(Token::Result(result)) = self.peek() {
+            let result = result.to_string();
+            self.index += 1;
+            game.set_tag("Result", &result);
+        }
+        Ok(game)
+    }
+
+    /// Reads moves until the end of the variation or of the game
+    fn line(
+        &mut self,
+        mut board: Board,
+        depth: usize,
+        pending_comment: &mut Option<String>,
+    ) -> Result<Vec<Node>, PgnError> {
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut previous = board.clone();
+
+        loop {
+            match self.peek() {
+                Some(Token::Move(san)) => {
+                    let san = *san;
+                    let mv = parse_san(&board, san)
+                        .map_err(|_| PgnError::IllegalMove(san.to_string()))?;
+                    let mut node = Node::new(mv);
+                    node.starting_comment = pending_comment.take();
+                    nodes.push(node);
+                    previous = board.clone();
+                    board.play_unchecked(mv);
+                }
+                Some(Token::Nag(nag)) => {
+                    let nag = *nag;
+                    if let Some(node) = nodes.last_mut() {
+                        node.nags.push(nag);
+                    }
+                }
+                Some(Token::Comment(comment)) => {
+                    let comment = comment.trim().to_string();
+                    let target = match nodes.last_mut() {
+                        Some(node) => &mut node.comment,
+                        None => pending_comment,
+                    };
+                    match target {
+                        Some(existing) => {
+                            existing.push(' ');
+                            existing.push_str(&comment);
+                        }
+                        None => *target = Some(comment),
+                    }
+                }
+                Some(Token::Open) => {
+                    self.index += 1;
+                    let Some(node) = nodes.last_mut() else {
+                        return Err(PgnError::Syntax(String::from(
+                            "variation before the first move",
+                        )));
+                    };
+                    let variation = self.line(previous.clone(), depth + 1, &mut None)?;
+                    node.variations.push(variation);
+                    continue;
+                }
+                Some(Token::Close) if depth > 0 => {
+                    self.index += 1;
+                    return Ok(nodes);
+                }
+                Some(Token::Close) => {
+                    return Err(PgnError::Syntax(String::from("unopened variation")));
+                }
+                // The end of the game, or the start of the next one
+                Some(Token::Result(_) | Token::Tag(..)) | None if depth == 0 => {
+                    return Ok(nodes);
+                }
+                Some(Token::Result(_) | Token::Tag(..)) | None => {
+                    return Err(PgnError::Syntax(String::from("unclosed variation")));
+                }
+            }
+            self.index += 1;
+        }
+    }
+
+    /// Skips the rest of a game that couldn't be read
+    fn skip_game(&mut self) {
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Tag(..) => return,
+                Token::Result(_) => {
+                    self.index += 1;
+                    return;
+                }
+                _ => self.index += 1,
+            }
+        }
+    }
+}
+
+impl Iterator for Reader<'_> {
+    type Item = Result<Game, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.peek()?;
+        let game = self.game();
+        if game.is_err() {
+            self.skip_game();
+        }
+        Some(game)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Tag(&'a str, String),
+    Comment(&'a str),
+    Nag(u8),
+    Open,
+    Close,
+    Result(&'a str),
+    Move(&'a str),
+}
+
+/// Suffix annotations and the NAGs they stand for
+const SUFFIXES: [(&str, u8); 6] = [
+    ("!!", 3),
+    ("??", 4),
+    ("!?", 5),
+    ("?!", 6),
+    ("!", 1),
+    ("?", 2),
+];
+
+fn tokenize(pgn: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = pgn;
+
+    while let Some(c) = rest.chars().next() {
+        let len = c.len_utf8();
+        match c {
+            '[' => {
+                let end = rest.find(']').unwrap_or(rest.len());
+                let tag = &rest[1..end];
+                rest = rest.get(end + 1..).unwrap_or("");
+                if let Some((name, value)) = tag.trim().split_once(char::is_whitespace) {
+                    let value = value.trim().trim_matches('"');
+                    let value = value.replace("\\\"", "\"").replace("\\\\", "\\");
+                    tokens.push(Token::Tag(name, value));
+                }
+            }
+            '{' => {
+                let end = rest.find('}').unwrap_or(rest.len());
+                tokens.push(Token::Comment(&rest[1..end]));
+                rest = rest.get(end + 1..).unwrap_or("");
+            }
+            ';' => {
+                let end = rest.find('\n').unwrap_or(rest.len());
+                tokens.push(Token::Comment(&rest[1..end]));
+                rest = &rest[end..];
+            }
+            // Escaped lines
+            '%' if pgn.len() == rest.len() || pgn[..pgn.len() - rest.len()].ends_with('\n') => {
+                let end = rest.find('\n').unwrap_or(rest.len());
+                rest = &rest[end..];
+            }
+            '(' => {
+                tokens.push(Token::Open);
+                rest = &rest[1..];
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                rest = &rest[1..];
+            }
+            '*' => {
+                tokens.push(Token::Result("*"));
+                rest = &rest[1..];
+            }
+            '$' => {
+                let end = rest[1..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .map_or(rest.len(), |i| i + 1);
+                if let Ok(nag) = rest[1..end].parse() {
+                    tokens.push(Token::Nag(nag));
+                }
+                rest = &rest[end..];
+            }
+            c if c.is_alphanumeric() => {
+                let end = rest
+                    .find(|c: char| !(c.is_alphanumeric() || "_+#=:-/".contains(c)))
+                    .unwrap_or(rest.len());
+                let symbol = &rest[..end];
+                rest = &rest[end..];
+
+                if matches!(symbol, "1-0" | "0-1" | "1/2-1/2") {
+                    tokens.push(Token::Result(symbol));
+                } else if symbol.chars().all(|c| c.is_ascii_digit()) {
+                    // Move numbers
+                    rest = rest.trim_start_matches('.');
+                } else {
+                    tokens.push(Token::Move(symbol));
+                    if let Some(&(suffix, nag)) =
+                        SUFFIXES.iter().find(|(suffix, _)| rest.starts_with(suffix))
+                    {
+                        tokens.push(Token::Nag(nag));
+                        rest = &rest[suffix.len()..];
+                    }
+                }
+            }
+            _ => rest = &rest[len..],
+        }
+    }
+    tokens
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{} \"{}\"]", name, value)?;
+        }
+        writeln!(f)?;
+
+        let mut words = Vec::new();
+        if let Some(comment) = &self.comment {
+            words.push(format!("{{{}}}", comment));
+        }
+        write_line(&self.start, &self.moves, &mut words);
+        words.push(self.result().to_string());
+
+        let mut line = String::new();
+        for word in words {
+            if !line.is_empty() && line.len() + 1 + word.len() > LINE_WIDTH {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        writeln!(f, "{}", line)
+    }
+}
+
+/// Adds the words of a line of moves, with its variations
+fn write_line(board: &Board, nodes: &[Node], words: &mut Vec<String>) {
+    let mut board = board.clone();
+    // Black moves need their number after anything that interrupts the moves
+    let mut needs_number = true;
+
+    for node in nodes {
+        if let Some(comment) = &node.starting_comment {
+            words.push(format!("{{{}}}", comment));
+        }
+
+        let number = board.fullmove_number();
+        let san = to_san(&board, node.mv);
+        match board.side_to_move() {
+            Color::White => words.push(format!("{}. {}", number, san)),
+            Color::Black if needs_number => words.push(format!("{}... {}", number, san)),
+            Color::Black => words.push(san),
+        }
+        needs_number = false;
+
+        for nag in &node.nags {
+            words.push(format!("${}", nag));
+        }
+        if let Some(comment) = &node.comment {
+            words.push(format!("{{{}}}", comment));
+            needs_number = true;
+        }
+        for variation in &node.variations {
+            let mut variation_words = Vec::new();
+            write_line(&board, variation, &mut variation_words);
+            if let Some(first) = variation_words.first_mut() {
+                first.insert(0, '(');
+            }
+            if let Some(last) = variation_words.last_mut() {
+                last.push(')');
+            }
+            words.extend(variation_words);
+            needs_number = true;
+        }
+
+        board.play_unchecked(node.mv);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGN: &str = r#"[Event "Casual game"]
+[Site "?"]
+[Date "2024.01.01"]
+[Round "?"]
+[White "Tsunami"]
+[Black "Tsunami"]
+[Result "1-0"]
+
+{A short game} 1. e4 e5 2. Nf3 Nc6 3. Bb5 $1 {The Ruy Lopez} (3. Bc4 Bc5
+(3... Nf6) 4. c3) 3... a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O
+9. h3 Nb8 10. d4 Nbd7 11. Nbd2 1-0
+"#;
+
+    #[test]
+    // Reading a game and writing it back gives the same PGN
+    fn test_pgn_round_trip() {
+        let games = parse(PGN).unwrap();
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+        assert_eq!(game.tag("White"), Some("Tsunami"));
+        assert_eq!(game.white_score(), Some(1.0));
+        assert_eq!(game.moves.len(), 21);
+        assert_eq!(game.moves[4].nags, [1]);
+        assert_eq!(game.moves[4].variations[0][1].variations.len(), 1);
+        assert_eq!(game.moves[8].mv, "e1h1".parse().unwrap());
+        assert_eq!(game.comment.as_deref(), Some("A short game"));
+        assert_eq!(game.to_string(), PGN);
+    }
+
+    #[test]
+    fn test_invalid_games() {
+        let pgn = "1. e4 e5 2. Ke3 1-0\n\n1. d4 (1... d5) 0-1\n\n1. c4 c5 1/2-1/2";
+        let games: Vec<_> = Reader::new(pgn).collect();
+        assert_eq!(games.len(), 3);
+        assert_eq!(
+            games[0].as_ref().unwrap_err(),
+            &PgnError::IllegalMove(String::from("Ke3"))
+        );
+        assert!(games[1].is_err());
+        assert_eq!(games[2].as_ref().unwrap().result(), "1/2-1/2");
+    }
+}
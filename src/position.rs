@@ -1,10 +1,29 @@
-use cozy_chess::{Board, Move, Piece, Square};
+use std::sync::Arc;
 
-use crate::nnue::{Accumulator, Network};
+use cozy_chess::{Board, Color, File, Move, Piece, Square};
+
+use crate::{
+    chess960,
+    error::Error,
+    hce,
+    nnue::{self, refresh::refresh, Accumulator, Network, View},
+};
 
 pub const SEE_VALS: [i32; 6] = [100, 450, 450, 650, 1250, 0];
+/// The network output is scaled by `(SCALE_BASE + material / 32) / 1024`
+pub const SCALE_BASE: i32 = 700;
+
+/// Every legal move, castling is the king taking its own rook
+pub fn legal_moves(board: &Board) -> Vec<Move> {
+    let mut moves = Vec::new();
+    board.generate_moves(|mvs| {
+        moves.extend(mvs);
+        false
+    });
+    moves
+}
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct Position {
     // cozy_chess board
     pub board: Board,
@@ -12,20 +31,70 @@ pub struct Position {
     pub acc: [Accumulator; 2],
     // Stores hashes of previous positions
     pub repeition_history: Vec<u64>,
+    /// The network the accumulators are built with
+    net: Arc<Network>,
+    /// Evaluates with the classical evaluation instead of the network
+    pub classical: bool,
+}
+
+impl Clone for Position {
+    fn clone(&self) -> Self {
+        Self {
+            board: self.board.clone(),
+            acc: self.acc,
+            repeition_history: self.repeition_history.clone(),
+            net: Arc::clone(&self.net),
+            classical: self.classical,
+        }
+    }
+
+    /// Reuses the repetition history buffer and, if it is the same, the
+    /// network, so that the search can copy positions cheaply
+    fn clone_from(&mut self, source: &Self) {
+        self.board.clone_from(&source.board);
+        self.acc = source.acc;
+        self.repeition_history.clone_from(&source.repeition_history);
+        if !Arc::ptr_eq(&self.net, &source.net) {
+            self.net = Arc::clone(&source.net);
+        }
+        self.classical = source.classical;
+    }
 }
 
 impl Position {
+    /// Panics on an invalid FEN, see `try_from_fen`
     pub fn from_fen(fen: &str) -> Self {
-        let board = Board::from_fen(fen, false).unwrap();
+        Self::try_from_fen(fen).unwrap()
+    }
+
+    /// Evaluates with the embedded network, see `try_from_fen_with`
+    pub fn try_from_fen(fen: &str) -> Result<Self, Error> {
+        Self::try_from_fen_with(fen, nnue::embedded_network())
+    }
+
+    pub fn try_from_fen_with(fen: &str, net: Arc<Network>) -> Result<Self, Error> {
+        let board = chess960::parse_fen(fen).map_err(|_| Error::InvalidFen(fen.to_string()))?;
 
         let mut pos = Self {
             board,
-            acc: [Accumulator::default(), Accumulator::default()],
+            acc: [Accumulator::new(&net); 2],
             repeition_history: Vec::new(),
+            net,
+            classical: false,
         };
 
         pos.update_acc();
-        pos
+        Ok(pos)
+    }
+
+    pub fn network(&self) -> &Arc<Network> {
+        &self.net
+    }
+
+    /// Evaluates with `net` from now on
+    pub fn set_network(&mut self, net: Arc<Network>) {
+        self.net = net;
+        self.update_acc();
     }
 
     /// Inserts a new hash into the repetition history
@@ -45,94 +114,117 @@ impl Position {
     }
 
     /// Goes through every square to update the accumulators
-    fn update_acc(&mut self) {
-        self.acc = [Accumulator::default(), Accumulator::default()];
-        // Update accumulators
-        for sq in 0..64 {
-            if let Some(pc) = self.board.piece_on(Square::index(sq)) {
-                let color = self.board.color_on(Square::index(sq)).unwrap();
-                self.toggle::<true>(color as usize, pc as usize, sq);
+    pub fn update_acc(&mut self) {
+        for color in Color::ALL {
+            let view = View::new(&self.net, &self.board, color);
+            let acc = &mut self.acc[color as usize];
+            *acc = Accumulator::new(&self.net);
+
+            for sq in self.board.occupied() {
+                let pc = self.board.piece_on(sq).unwrap();
+                let pc_color = self.board.color_on(sq).unwrap();
+                acc.add(&self.net, view.feature(pc_color, pc, sq));
             }
         }
     }
 
-    /// Makes a move on the board
-    /// Also updates the accumulators and the repetition history
-    pub fn make_move(&mut self, mv: Move) {
-        // If it is a capture, update the accumulator for the captured piece
-        if self.board.piece_on(mv.to).is_some() {
-            self.toggle::<false>(
-                self.board.side_to_move() as usize ^ 1,
-                self.board.piece_on(mv.to).unwrap() as usize,
-                mv.to as usize,
-            );
+    /// Makes a move after checking it is legal
+    pub fn try_make_move(&mut self, mv: Move) -> Result<(), Error> {
+        if !self.board.is_legal(mv) {
+            return Err(Error::IllegalMove(mv.to_string()));
         }
+        self.make_move(mv);
+        Ok(())
+    }
 
-        // If it is a promotion, update the accumulator for the promoted piece
-        if mv.promotion.is_some() {
-            self.toggle::<false>(
-                self.board.side_to_move() as usize,
-                Piece::Pawn as usize,
-                mv.to as usize,
-            );
-            self.toggle::<true>(
-                self.board.side_to_move() as usize,
-                mv.promotion.unwrap() as usize,
-                mv.to as usize,
-            );
-
-            self.board.play_unchecked(mv);
-            self.update_repetitions();
-
-            return;
-        }
+    /// Makes a move on the board, which has to be legal
+    /// Also updates the accumulators and the repetition history
+    pub fn make_move(&mut self, mv: Move) {
+        let side = self.board.side_to_move();
+        let moved = self.board.piece_on(mv.from).unwrap();
+        let net = &self.net;
+        let views = [
+            View::new(net, &self.board, Color::White),
+            View::new(net, &self.board, Color::Black),
+        ];
 
-        // If it is a castling move, update the accumulator for the rook and king
-        if self.board.color_on(mv.to) == self.board.color_on(mv.from) {
-            self.board.play_unchecked(mv);
+        // If it is a castling move, the king "captures" its own rook and both
+        // end up on the same squares as in standard chess
+        let castling = self.board.color_on(mv.to) == Some(side);
 
-            // Just reset the accumulators
-            // TODO: This is quick but should be changed to incrementally update accumulators
-            self.update_acc();
-            self.update_repetitions();
+        // The captured piece, if any. A pawn moving diagonally to an
+        // empty square captures en passant, behind the destination square
+        let captured = match self.board.piece_on(mv.to) {
+            _ if castling => None,
+            Some(pc) => Some((pc, mv.to)),
+            None if moved == Piece::Pawn && mv.from.file() != mv.to.file() => {
+                Some((Piece::Pawn, Square::new(mv.to.file(), mv.from.rank())))
+            }
+            None => None,
+        };
 
-            return;
-        }
+        self.board.play_unchecked(mv);
 
         // Update the accumulators
-        self.toggle::<false>(
-            self.board.side_to_move() as usize,
-            self.board.piece_on(mv.from).unwrap() as usize,
-            mv.from as usize,
-        );
-
-        self.toggle::<true>(
-            self.board.side_to_move() as usize,
-            self.board.piece_on(mv.from).unwrap() as usize,
-            mv.to as usize,
-        );
+        for (view, acc) in views.into_iter().zip(&mut self.acc) {
+            // When the king changes bucket, every feature changes
+            if moved == Piece::King && view.perspective() == side {
+                let new_view = View::new(net, &self.board, side);
+                if new_view != view {
+                    refresh(net, acc, &self.board, new_view);
+                    continue;
+                }
+            }
 
-        self.board.play_unchecked(mv);
+            if castling {
+                let (king_file, rook_file) = if mv.to.file() > mv.from.file() {
+                    (File::G, File::F)
+                } else {
+                    (File::C, File::D)
+                };
+                let rank = mv.from.rank();
+                acc.add_add_sub_sub(
+                    net,
+                    view.feature(side, Piece::King, Square::new(king_file, rank)),
+                    view.feature(side, Piece::Rook, Square::new(rook_file, rank)),
+                    view.feature(side, Piece::King, mv.from),
+                    view.feature(side, Piece::Rook, mv.to),
+                );
+                continue;
+            }
+
+            // A promoting pawn turns into the promoted piece on the destination square
+            let from = view.feature(side, moved, mv.from);
+            let to = view.feature(side, mv.promotion.unwrap_or(moved), mv.to);
+            match captured {
+                Some((pc, sq)) => acc.add_sub_sub(net, to, from, view.feature(!side, pc, sq)),
+                None => acc.add_sub(net, to, from),
+            }
+        }
 
         // Update repetitions history
         self.update_repetitions();
     }
 
-    fn toggle<const ADD: bool>(&mut self, side: usize, pc: usize, sq: usize) {
-        // Find the index to update in the accumulator
-        let start = 384 * side + 64 * pc + sq;
-        self.acc[0].update::<ADD>(start);
-
-        let start = 384 * (side ^ 1) + 64 * pc + (sq ^ 56);
-        self.acc[1].update::<ADD>(start);
-    }
-
-    /// The NNUE evaluation of the current position
+    /// The static evaluation of the current position, from the network or from
+    /// the classical evaluation when it is selected
     pub fn eval(&self) -> i32 {
+        if self.classical {
+            return hce::evaluate(&self.board);
+        }
+
         let boys = &self.acc[self.board.side_to_move() as usize];
         let opps = &self.acc[self.board.side_to_move() as usize ^ 1];
-        let eval = Network::out(boys, opps);
-        self.scale(eval)
+        let eval = self
+            .net
+            .out(boys, opps, self.board.occupied().len() as usize);
+
+        // Networks with output buckets already specialise on the material
+        if self.net.arch().output_buckets > 1 {
+            eval
+        } else {
+            self.scale(eval)
+        }
     }
 
     /// Scales the evaluation based on the material on the board
@@ -143,8 +235,51 @@ impl Position {
             + self.board.pieces(Piece::Rook).len() as i32 * SEE_VALS[Piece::Rook as usize]
             + self.board.pieces(Piece::Queen).len() as i32 * SEE_VALS[Piece::Queen as usize];
 
-        mat = 700 + mat / 32;
+        mat = SCALE_BASE + mat / 32;
 
         eval * mat / 1024
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Incrementally updated accumulators have to match ones built from scratch,
+    // including after en passant, promotions with captures and castling
+    fn test_incremental_accumulators() {
+        let mut pos = Position::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1");
+        for mv in ["e5d6", "e8h8", "b7a8q", "f8a8", "e1a1"] {
+            pos.make_move(mv.parse().unwrap());
+
+            let mut fresh = pos.clone();
+            fresh.update_acc();
+            assert_eq!(pos.eval(), fresh.eval(), "after {}", mv);
+        }
+
+        // Chess960 castling where the king or the rook doesn't move
+        let mut pos = Position::from_fen("1r3kr1/8/8/8/8/8/8/1R3KR1 w GBgb - 0 1");
+        for mv in ["f1g1", "f8b8"] {
+            pos.make_move(mv.parse().unwrap());
+
+            let mut fresh = pos.clone();
+            fresh.update_acc();
+            assert_eq!(pos.eval(), fresh.eval(), "after {}", mv);
+        }
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(Position::try_from_fen("8/8/8/8 w - - 0 1").is_err());
+        assert!(Position::try_from_fen("k7/8/8/8/8/8/8/K6R w KQ - 0 1").is_err());
+
+        let mut pos = Position::try_from_fen("k7/8/8/8/8/8/8/K6R w - - 0 1").unwrap();
+        assert!(pos.try_make_move("h1h8".parse().unwrap()).is_ok());
+        assert!(matches!(
+            pos.try_make_move("h8g8".parse().unwrap()),
+            Err(Error::IllegalMove(_))
+        ));
+        assert_eq!(pos.board.to_string(), "k6R/8/8/8/8/8/8/K7 b - - 1 1");
+    }
+}
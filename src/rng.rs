@@ -0,0 +1,31 @@
+use crate::clock;
+
+/// A small xorshift64* pseudo-random number generator
+/// Not suitable for anything but picking moves
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero
+        Self(seed ^ 0x9E37_79B9_7F4A_7C15 | 1)
+    }
+
+    /// Seeded from the clock, mixed with `salt` so generators created at the
+    /// same time (for example one per thread) differ
+    pub fn from_time(salt: u64) -> Self {
+        Self::new(clock::time_seed() ^ salt.wrapping_mul(0xD1B5_4A32_D192_ED03))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A number in `0..n`
+    pub fn below(&mut self, n: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(n)) >> 64) as u64
+    }
+}
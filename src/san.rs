@@ -0,0 +1,231 @@
+//! Standard Algebraic Notation
+//!
+//! cozy-chess represents castling as the king capturing its own rook, SAN
+//! writes it as `O-O` or `O-O-O`.
+
+use std::fmt::{self, Write};
+
+use cozy_chess::{Board, File, GameStatus, Move, Piece, Rank, Square};
+
+use crate::position::legal_moves;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SanError {
+    /// Not a SAN move at all
+    Invalid,
+    /// No legal move matches
+    Illegal,
+    /// More than one legal move matches
+    Ambiguous,
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::Invalid => write!(f, "invalid SAN"),
+            SanError::Illegal => write!(f, "illegal move"),
+            SanError::Ambiguous => write!(f, "ambiguous move"),
+        }
+    }
+}
+
+/// Finds the legal move a SAN move refers to
+/// Check, mate and annotation suffixes are ignored, and promotions may leave
+/// out the `=`
+pub fn parse_san(board: &Board, san: &str) -> Result<Move, SanError> {
+    let san = san.trim_end_matches(['+', '#', '!', '?']);
+    let moves = legal_moves(board);
+
+    if let Some(long) = match san {
+        "O-O" | "0-0" => Some(false),
+        "O-O-O" | "0-0-0" => Some(true),
+        _ => None,
+    } {
+        let king = board.king(board.side_to_move());
+        return moves
+            .into_iter()
+            .find(|mv| {
+                mv.from == king
+                    && board.colors(board.side_to_move()).has(mv.to)
+                    && (mv.to.file() < king.file()) == long
+            })
+            .ok_or(SanError::Illegal);
+    }
+
+    let (piece, rest) = match san.chars().next().ok_or(SanError::Invalid)? {
+        'N' => (Piece::Knight, &san[1..]),
+        'B' => (Piece::Bishop, &san[1..]),
+        'R' => (Piece::Rook, &san[1..]),
+        'Q' => (Piece::Queen, &san[1..]),
+        'K' => (Piece::King, &san[1..]),
+        'a'..='h' => (Piece::Pawn, san),
+        _ => return Err(SanError::Invalid),
+    };
+
+    let promotion = match rest.chars().last() {
+        Some('N') => Some(Piece::Knight),
+        Some('B') => Some(Piece::Bishop),
+        Some('R') => Some(Piece::Rook),
+        Some('Q') => Some(Piece::Queen),
+        _ => None,
+    };
+    let rest = match promotion {
+        Some(_) => rest[..rest.len() - 1].trim_end_matches('='),
+        None => rest,
+    };
+
+    let rest: Vec<char> = rest.chars().filter(|&c| c != 'x').collect();
+    if rest.len() < 2 {
+        return Err(SanError::Invalid);
+    }
+    let (from, to) = rest.split_at(rest.len() - 2);
+    let to = parse_square(to[0], to[1]).ok_or(SanError::Invalid)?;
+    let mut from_file = None;
+    let mut from_rank = None;
+    for &c in from {
+        match c {
+            'a'..='h' => from_file = Some(File::index(c as usize - 'a' as usize)),
+            '1'..='8' => from_rank = Some(Rank::index(c as usize - '1' as usize)),
+            _ => return Err(SanError::Invalid),
+        }
+    }
+
+    let mut candidates = moves.into_iter().filter(|mv| {
+        board.piece_on(mv.from) == Some(piece)
+            && mv.to == to
+            && mv.promotion == promotion
+            && from_file.is_none_or(|file| mv.from.file() == file)
+            && from_rank.is_none_or(|rank| mv.from.rank() == rank)
+            // The king moving two squares is written as castling
+            && !(piece == Piece::King && board.colors(board.side_to_move()).has(mv.to))
+    });
+    let mv = candidates.next().ok_or(SanError::Illegal)?;
+    match candidates.next() {
+        Some(_) => Err(SanError::Ambiguous),
+        None => Ok(mv),
+    }
+}
+
+fn parse_square(file: char, rank: char) -> Option<Square> {
+    Some(Square::new(
+        File::try_index((file as usize).checked_sub('a' as usize)?)?,
+        Rank::try_index((rank as usize).checked_sub('1' as usize)?)?,
+    ))
+}
+
+/// The SAN of a legal move
+pub fn to_san(board: &Board, mv: Move) -> String {
+    let mut san = String::new();
+    let piece = board.piece_on(mv.from).unwrap();
+
+    if piece == Piece::King && board.colors(board.side_to_move()).has(mv.to) {
+        san.push_str(if mv.to.file() > mv.from.file() {
+            "O-O"
+        } else {
+            "O-O-O"
+        });
+    } else {
+        let capture =
+            board.occupied().has(mv.to) || (piece == Piece::Pawn && mv.from.file() != mv.to.file());
+
+        if piece == Piece::Pawn {
+            if capture {
+                san.push(char::from(mv.from.file()));
+            }
+        } else {
+            san.push_str(&piece.to_string().to_uppercase());
+
+            // Other pieces of the same kind that could move there
+            let others: Vec<Square> = legal_moves(board)
+                .into_iter()
+                .filter(|other| {
+                    other.to == mv.to
+                        && other.from != mv.from
+                        && board.piece_on(other.from) == Some(piece)
+                })
+                .map(|other| other.from)
+                .collect();
+            if !others.is_empty() {
+                if others.iter().all(|sq| sq.file() != mv.from.file()) {
+                    san.push(char::from(mv.from.file()));
+                } else if others.iter().all(|sq| sq.rank() != mv.from.rank()) {
+                    san.push(char::from(mv.from.rank()));
+                } else {
+                    write!(san, "{}", mv.from).unwrap();
+                }
+            }
+        }
+
+        if capture {
+            san.push('x');
+        }
+        write!(san, "{}", mv.to).unwrap();
+        if let Some(promotion) = mv.promotion {
+            write!(san, "={}", promotion.to_string().to_uppercase()).unwrap();
+        }
+    }
+
+    let mut after = board.clone();
+    after.play_unchecked(mv);
+    if after.status() == GameStatus::Won {
+        san.push('#');
+    } else if !after.checkers().is_empty() {
+        san.push('+');
+    }
+    san
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_san() {
+        let board = Board::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            false,
+        )
+        .unwrap();
+        let cases = [
+            ("e1h1", "O-O"),
+            ("e1a1", "O-O-O"),
+            ("e5f7", "Nxf7"),
+            ("d5e6", "dxe6"),
+            ("c3b1", "Nb1"),
+            ("e2a6", "Bxa6"),
+            ("f3f6", "Qxf6"),
+        ];
+        for (uci, san) in cases {
+            let mv: Move = uci.parse().unwrap();
+            assert_eq!(to_san(&board, mv), san);
+            assert_eq!(parse_san(&board, san), Ok(mv));
+        }
+
+        // Disambiguation by file, then by rank, then by both
+        let board = Board::from_fen("1k6/8/8/8/R6R/8/8/R3K3 w - - 0 1", false).unwrap();
+        for (uci, san) in [("a4d4", "Rad4"), ("a1a2", "R1a2"), ("a4a7", "Ra7")] {
+            let mv: Move = uci.parse().unwrap();
+            assert_eq!(to_san(&board, mv), san);
+            assert_eq!(parse_san(&board, san), Ok(mv));
+        }
+        assert_eq!(parse_san(&board, "Rd4"), Err(SanError::Ambiguous));
+        assert_eq!(parse_san(&board, "Rd5"), Err(SanError::Illegal));
+        assert_eq!(parse_san(&board, "Zz9"), Err(SanError::Invalid));
+
+        let board = Board::from_fen("3r2k1/2P5/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", false).unwrap();
+        let mv: Move = "a1c3".parse().unwrap();
+        assert_eq!(to_san(&board, mv), "Qa1c3");
+        assert_eq!(parse_san(&board, "Qa1c3"), Ok(mv));
+
+        // Promotions with check, and mate after long castling
+        let mv: Move = "c7d8q".parse().unwrap();
+        assert_eq!(to_san(&board, mv), "cxd8=Q+");
+        assert_eq!(parse_san(&board, "cxd8=Q+"), Ok(mv));
+        assert_eq!(parse_san(&board, "cxd8Q"), Ok(mv));
+
+        let board = Board::from_fen("2rkr3/2p1p3/8/8/8/8/8/R3K3 w Q - 0 1", false).unwrap();
+        let mv: Move = "e1a1".parse().unwrap();
+        assert_eq!(to_san(&board, mv), "O-O-O#");
+        assert_eq!(parse_san(&board, "O-O-O#"), Ok(mv));
+    }
+}
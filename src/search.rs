@@ -1,21 +1,88 @@
-use std::time::Instant;
+pub mod report;
 
-use crate::{move_ordering::mvv_lva, position::Position};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    clock::Instant, correction_history::CorrectionHistory, move_ordering::mvv_lva,
+    position::Position, tunable,
+};
 use cozy_chess::Move;
+use report::SearchReporter;
 
 const CHECKMATE: i32 = 100000;
 const DRAW: i32 = 0;
+/// Scores beyond this are mate scores
+const MATE_BOUND: i32 = CHECKMATE - 1000;
+/// How often the clock is checked
+const TIME_CHECK_NODES: u64 = 4096;
 
-#[derive(Clone, Copy, Debug)]
-struct SearchInfo {
+/// The result of one iteration of the search
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
     pub best_move: Option<Move>,
     pub depth: u8,
     pub nodes: u64,
     pub eval: i32,
+    /// The principal variation, starting with the best move
+    pub pv: Vec<Move>,
+}
+
+/// The result of a whole search
+#[derive(Clone, Debug, Default)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    /// The reply to the best move the search expects
+    pub ponder_move: Option<Move>,
+    pub eval: i32,
+    /// Of the last completed iteration
+    pub depth: u8,
+    pub pv: Vec<Move>,
+    /// Over every iteration
+    pub nodes: u64,
+    pub time_millis: u128,
+}
+
+/// What the search carries between nodes and iterations
+struct SearchState<'a> {
+    corr_hist: CorrectionHistory,
+    stop: &'a AtomicBool,
+    start_time: Instant,
+    time_limit_millis: u128,
+    node_limit: u64,
+    /// Of the completed iterations
+    nodes: u64,
+    /// Set once a limit is reached or the stop flag is seen, the search then
+    /// unwinds
+    stopped: bool,
+}
+
+impl<'a> SearchState<'a> {
+    fn new(stop: &'a AtomicBool, time_limit_millis: u128, node_limit: u64) -> Self {
+        Self {
+            corr_hist: CorrectionHistory::default(),
+            stop,
+            start_time: Instant::now(),
+            time_limit_millis,
+            node_limit,
+            nodes: 0,
+            stopped: false,
+        }
+    }
+
+    /// Whether the search has to stop after `iteration_nodes` nodes of the
+    /// current iteration
+    fn should_stop(&self, iteration_nodes: u64) -> bool {
+        let nodes = self.nodes + iteration_nodes;
+        nodes >= self.node_limit
+            || self.stop.load(Ordering::Relaxed)
+            || (nodes.is_multiple_of(TIME_CHECK_NODES)
+                && self.start_time.elapsed().as_millis() >= self.time_limit_millis)
+    }
 }
 
 /// The main search algorithm is negamax with alpha-beta pruning
 /// Negamax is a variant of minimax
+#[allow(clippy::too_many_arguments)]
 fn negamax(
     mut alpha: i32,
     beta: i32,
@@ -23,7 +90,16 @@ fn negamax(
     pos: &mut Position,
     ply: u32,
     search_info: &mut SearchInfo,
+    state: &mut SearchState,
+    pv: &mut Vec<Move>,
 ) -> i32 {
+    // The result of a stopped search is thrown away
+    // The first iteration always completes, to have a move to play
+    if state.stopped || (search_info.depth > 1 && state.should_stop(search_info.nodes)) {
+        state.stopped = true;
+        return 0;
+    }
+
     // Visiting one more node
     search_info.nodes += 1;
 
@@ -32,6 +108,25 @@ fn negamax(
         return pos.eval();
     }
 
+    let in_check = !pos.board.checkers().is_empty();
+    let original_alpha = alpha;
+
+    // The static evaluation, corrected by what previous searches of this
+    // pawn structure have learned. Only used for pruning decisions
+    let raw_eval = pos.eval();
+    let static_eval = state.corr_hist.correct(pos, raw_eval);
+
+    // Reverse futility pruning
+    // If we are so far ahead that even a margin per ply can't bring us
+    // below beta, assume this node will fail high
+    // This is the pruning decision the corrected evaluation is for
+    if !in_check
+        && i32::from(depth) <= tunable::rfp_depth()
+        && static_eval - tunable::rfp_margin() * i32::from(depth) >= beta
+    {
+        return static_eval;
+    }
+
     // An average position has ~32 moves.
     // TODO: It might be better to use a bigger capacity here anyway
     let mut moves: Vec<Move> = Vec::with_capacity(32);
@@ -51,7 +146,7 @@ fn negamax(
 
     // TODO: cozy_chess has a function for this
     if moves.is_empty() {
-        return if !pos.board.checkers().is_empty() {
+        return if in_check {
             // No moves and in check, checkmate
             // We want to return the score relative to the current ply so that
             // We find checkmate in the least moves possible
@@ -69,9 +164,14 @@ fn negamax(
     sort_moves(&mut moves, pos);
 
     let mut best_score = -CHECKMATE;
+    let mut best_move = None;
 
-    for mv in moves {
-        let mut new_pos = pos.clone();
+    // Every child is made in the same copy of the position
+    let mut new_pos = pos.clone();
+    for (i, mv) in moves.into_iter().enumerate() {
+        if i > 0 {
+            new_pos.clone_from(pos);
+        }
         new_pos.make_move(mv);
 
         // Check for a 3-fold repetition
@@ -79,11 +179,27 @@ fn negamax(
         if pos.is_repetition(new_pos.board.hash()) {
             return DRAW;
         }
-        let score = -negamax(-beta, -alpha, depth - 1, &mut new_pos, ply + 1, search_info);
-        best_score = best_score.max(score);
+        let mut child_pv = Vec::new();
+        let score = -negamax(
+            -beta,
+            -alpha,
+            depth - 1,
+            &mut new_pos,
+            ply + 1,
+            search_info,
+            state,
+            &mut child_pv,
+        );
+        if score > best_score {
+            best_score = score;
+            best_move = Some(mv);
+        }
 
         if score > alpha {
             alpha = score;
+            pv.clear();
+            pv.push(mv);
+            pv.extend(child_pv);
         }
 
         // Fail-hard beta cutoff
@@ -92,6 +208,15 @@ fn negamax(
         }
     }
 
+    // Teach the correction history about this node, unless the result is
+    // only a bound pointing the same way as the static eval already does
+    let best_is_capture = best_move.is_some_and(|mv| captures.contains(&mv));
+    let uninformative_bound = (best_score >= beta && best_score <= static_eval)
+        || (best_score <= original_alpha && best_score >= static_eval);
+    if !in_check && !best_is_capture && !uninformative_bound && best_score.abs() < MATE_BOUND {
+        state.corr_hist.update(pos, depth, best_score, raw_eval);
+    }
+
     best_score
 }
 
@@ -106,9 +231,9 @@ fn sort_moves(moves: &mut [Move], pos: &Position) {
     for i in 0..moves.len() {
         let mut best_score = scores[i];
         let mut best_index = i;
-        for j in i + 1..moves.len() {
-            if scores[j] > best_score {
-                best_score = scores[j];
+        for (j, &score) in scores.iter().enumerate().skip(i + 1) {
+            if score > best_score {
+                best_score = score;
                 best_index = j;
             }
         }
@@ -120,40 +245,77 @@ fn sort_moves(moves: &mut [Move], pos: &Position) {
     }
 }
 
-/// The main entry point for the search
-/// UCI output
-pub fn think(pos: &mut Position, depth: u8, time_limit_millis: u128) {
-    let mut candidate_move: Option<Move> = None;
-    let start_time = Instant::now();
+/// Iterative deepening until the depth, the time limit or the node limit is
+/// reached, keeping the result of the last completed iteration
+pub fn search(
+    pos: &mut Position,
+    depth: u8,
+    time_limit_millis: u128,
+    node_limit: u64,
+    reporter: &mut impl SearchReporter,
+) -> SearchResult {
+    let stop = AtomicBool::new(false);
+    search_until(pos, depth, time_limit_millis, node_limit, &stop, reporter)
+}
+
+/// Like `search`, but also stops as soon as `stop` is set
+pub fn search_until(
+    pos: &mut Position,
+    depth: u8,
+    time_limit_millis: u128,
+    node_limit: u64,
+    stop: &AtomicBool,
+    reporter: &mut impl SearchReporter,
+) -> SearchResult {
+    let mut state = SearchState::new(stop, time_limit_millis, node_limit);
 
     // Iterative deepening
-    for d in 1..=depth {
-        // We are out of time
-        if Instant::now().duration_since(start_time).as_millis() >= time_limit_millis {
-            println!("bestmove {}", candidate_move.unwrap());
-            return;
+    let mut search_info = best_move(pos, 1, &mut state, reporter);
+    reporter.iteration(&search_info);
+    for d in 2..=depth {
+        state.nodes += search_info.nodes;
+
+        // We are out of time or nodes
+        if state.should_stop(0) || state.start_time.elapsed().as_millis() >= time_limit_millis {
+            search_info.nodes = 0;
+            break;
         }
 
-        let search_info = best_move(pos, d);
-        candidate_move = Some(search_info.best_move.unwrap());
-        println!(
-            "info depth {} score cp {} nodes {} pv {}",
-            search_info.depth,
-            search_info.eval,
-            search_info.nodes,
-            candidate_move.unwrap()
-        );
+        let info = best_move(pos, d, &mut state, reporter);
+        if state.stopped {
+            state.nodes += info.nodes;
+            search_info.nodes = 0;
+            break;
+        }
+        search_info = info;
+        reporter.iteration(&search_info);
     }
 
-    // We reached max depth
-    println!("bestmove {}", candidate_move.unwrap());
+    let result = SearchResult {
+        best_move: search_info.best_move,
+        ponder_move: search_info.pv.get(1).copied(),
+        eval: search_info.eval,
+        depth: search_info.depth,
+        pv: search_info.pv,
+        nodes: state.nodes + search_info.nodes,
+        time_millis: state.start_time.elapsed().as_millis(),
+    };
+    reporter.result(&result);
+    result
 }
 
-/// Get the best move for the current position using negamax
-fn best_move(pos: &mut Position, depth: u8) -> SearchInfo {
-    let mut best_move = None;
-    let mut best_eval = -CHECKMATE;
+/// Whether a score means one of the sides can force checkmate
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE_BOUND
+}
 
+/// Get the best move for the current position using negamax
+fn best_move(
+    pos: &mut Position,
+    depth: u8,
+    state: &mut SearchState,
+    reporter: &mut impl SearchReporter,
+) -> SearchInfo {
     let mut moves = Vec::with_capacity(32);
     pos.board.generate_moves(|mv| {
         moves.extend(mv);
@@ -161,15 +323,19 @@ fn best_move(pos: &mut Position, depth: u8) -> SearchInfo {
     });
 
     let mut search_info = SearchInfo {
-        best_move,
+        best_move: None,
         depth,
         nodes: 0,
-        eval: best_eval,
+        eval: -CHECKMATE,
+        pv: Vec::new(),
     };
 
-    for mv in moves {
+    for (i, mv) in moves.into_iter().enumerate() {
+        reporter.current_move(depth, mv, i + 1);
+
         let mut new_pos = pos.clone();
         new_pos.make_move(mv);
+        let mut child_pv = Vec::new();
         let score = -negamax(
             -CHECKMATE,
             CHECKMATE,
@@ -177,15 +343,22 @@ fn best_move(pos: &mut Position, depth: u8) -> SearchInfo {
             &mut new_pos,
             1,
             &mut search_info,
+            state,
+            &mut child_pv,
         );
-        if score > best_eval {
-            best_eval = score;
-            best_move = Some(mv);
+        if state.stopped {
+            break;
+        }
+        if score > search_info.eval {
+            search_info.eval = score;
+            search_info.best_move = Some(mv);
+            search_info.pv.clear();
+            search_info.pv.push(mv);
+            search_info.pv.extend(child_pv);
+            reporter.pv(&search_info);
         }
     }
 
-    search_info.best_move = best_move;
-    search_info.eval = best_eval;
     search_info
 }
 
@@ -193,6 +366,7 @@ fn best_move(pos: &mut Position, depth: u8) -> SearchInfo {
 mod tests {
     use super::*;
     use crate::position::Position;
+    use report::SilentReporter;
 
     #[test]
     // The engine should be able to find checkmate regardless of the evaluation function
@@ -200,9 +374,27 @@ mod tests {
         // Mate in 2
         let mut pos =
             Position::from_fen("r4rk1/p1pb3p/2p5/3p2pN/3Qp1Pq/4P2P/PPP2PK1/R5R1 b - - 1 21");
-        let search_info = best_move(&mut pos, 4);
+        let stop = AtomicBool::new(false);
+        let search_info = best_move(
+            &mut pos,
+            4,
+            &mut SearchState::new(&stop, u128::MAX, u64::MAX),
+            &mut SilentReporter,
+        );
         assert_eq!(search_info.best_move.unwrap(), "f8f2".parse().unwrap());
         // 3 ply to mate
         assert_eq!(search_info.eval, CHECKMATE - 3);
+        assert_eq!(search_info.pv.len(), 3);
+    }
+
+    #[test]
+    fn test_node_limit() {
+        // Searches stop in the middle of an iteration, at the limit
+        for fen in &crate::bench::FENS[..10] {
+            let mut pos = Position::from_fen(fen);
+            let result = search(&mut pos, 100, u128::MAX, 5000, &mut SilentReporter);
+            assert_eq!(result.nodes, 5000, "{}", fen);
+            assert!(result.best_move.is_some());
+        }
     }
 }
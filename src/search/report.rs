@@ -0,0 +1,265 @@
+//! Reporting the progress of a search
+//!
+//! The search calls a `SearchReporter` as it goes. The UCI reporter writes
+//! `info` and `bestmove` lines for GUIs, the JSON reporter writes one object
+//! per event for scripts and dashboards, and the silent one writes nothing.
+
+use std::io::Write;
+
+use cozy_chess::{Board, Move};
+
+use super::{is_mate_score, SearchInfo, SearchResult, CHECKMATE};
+use crate::{chess960, clock::Instant};
+
+/// UCI only sends the current move and intermediate PVs of long searches
+const UCI_DETAIL_DELAY_MILLIS: u128 = 3000;
+
+pub trait SearchReporter {
+    /// A root move is about to be searched, `number` counts from 1
+    fn current_move(&mut self, _depth: u8, _mv: Move, _number: usize) {}
+
+    /// The best root move of the iteration in progress changed
+    fn pv(&mut self, _info: &SearchInfo) {}
+
+    /// An iteration completed
+    fn iteration(&mut self, _info: &SearchInfo) {}
+
+    /// The search finished
+    fn result(&mut self, _result: &SearchResult) {}
+}
+
+/// Reports nothing, for tooling that only needs the result
+pub struct SilentReporter;
+
+impl SearchReporter for SilentReporter {}
+
+/// A closure is called for every completed iteration
+impl<F: FnMut(&SearchInfo)> SearchReporter for F {
+    fn iteration(&mut self, info: &SearchInfo) {
+        self(info);
+    }
+}
+
+/// Moves in UCI notation, played one after the other from `board`
+pub(crate) fn uci_moves(board: &Board, moves: &[Move], chess960: bool) -> Vec<String> {
+    let mut board = board.clone();
+    moves
+        .iter()
+        .map(|&mv| {
+            let uci = chess960::to_uci(&board, mv, chess960).to_string();
+            board.play_unchecked(mv);
+            uci
+        })
+        .collect()
+}
+
+/// `cp <centipawns>`, or `mate <moves>` with a negative number of moves when
+/// getting mated
+fn uci_score(eval: i32) -> String {
+    if is_mate_score(eval) {
+        let plies = CHECKMATE - eval.abs();
+        let moves = (plies + 1) / 2;
+        format!("mate {}", if eval > 0 { moves } else { -moves })
+    } else {
+        format!("cp {}", eval)
+    }
+}
+
+/// Writes UCI output, write errors are ignored as there is nobody left to
+/// report them to
+pub struct UciReporter<W: Write> {
+    board: Board,
+    chess960: bool,
+    out: W,
+    start: Instant,
+    /// Of the completed iterations
+    nodes: u64,
+}
+
+impl<W: Write> UciReporter<W> {
+    pub fn new(board: &Board, chess960: bool, out: W) -> Self {
+        Self {
+            board: board.clone(),
+            chess960,
+            out,
+            start: Instant::now(),
+            nodes: 0,
+        }
+    }
+
+    fn info(&mut self, info: &SearchInfo) {
+        let nodes = self.nodes + info.nodes;
+        let time = self.start.elapsed().as_millis();
+        let _ = writeln!(
+            self.out,
+            "info depth {} score {} nodes {} time {} nps {} pv {}",
+            info.depth,
+            uci_score(info.eval),
+            nodes,
+            time,
+            nodes as u128 * 1000 / time.max(1),
+            uci_moves(&self.board, &info.pv, self.chess960).join(" ")
+        );
+    }
+}
+
+impl<W: Write> SearchReporter for UciReporter<W> {
+    fn current_move(&mut self, depth: u8, mv: Move, number: usize) {
+        if self.start.elapsed().as_millis() >= UCI_DETAIL_DELAY_MILLIS {
+            let _ = writeln!(
+                self.out,
+                "info depth {} currmove {} currmovenumber {}",
+                depth,
+                chess960::to_uci(&self.board, mv, self.chess960),
+                number
+            );
+        }
+    }
+
+    fn pv(&mut self, info: &SearchInfo) {
+        if self.start.elapsed().as_millis() >= UCI_DETAIL_DELAY_MILLIS {
+            self.info(info);
+        }
+    }
+
+    fn iteration(&mut self, info: &SearchInfo) {
+        if info.best_move.is_some() {
+            self.info(info);
+        }
+        self.nodes += info.nodes;
+    }
+
+    fn result(&mut self, result: &SearchResult) {
+        // Checkmate or stalemate, there is no move to play
+        let moves = uci_moves(&self.board, &result.pv, self.chess960);
+        let _ = match moves.as_slice() {
+            [] => writeln!(self.out, "bestmove 0000"),
+            [best] => writeln!(self.out, "bestmove {}", best),
+            [best, ponder, ..] => writeln!(self.out, "bestmove {} ponder {}", best, ponder),
+        };
+        let _ = self.out.flush();
+    }
+}
+
+/// Writes one JSON object per line for every event
+pub struct JsonReporter<W: Write> {
+    board: Board,
+    chess960: bool,
+    out: W,
+    start: Instant,
+}
+
+impl<W: Write> JsonReporter<W> {
+    pub fn new(board: &Board, chess960: bool, out: W) -> Self {
+        Self {
+            board: board.clone(),
+            chess960,
+            out,
+            start: Instant::now(),
+        }
+    }
+
+    fn pv_json(&self, pv: &[Move]) -> String {
+        let moves: Vec<String> = uci_moves(&self.board, pv, self.chess960)
+            .into_iter()
+            .map(|mv| format!("\"{}\"", mv))
+            .collect();
+        format!("[{}]", moves.join(","))
+    }
+
+    fn info(&mut self, event: &str, info: &SearchInfo) {
+        let _ = writeln!(
+            self.out,
+            "{{\"event\":\"{}\",\"depth\":{},\"score\":{},\"nodes\":{},\"time\":{},\"pv\":{}}}",
+            event,
+            info.depth,
+            info.eval,
+            info.nodes,
+            self.start.elapsed().as_millis(),
+            self.pv_json(&info.pv)
+        );
+    }
+}
+
+impl<W: Write> SearchReporter for JsonReporter<W> {
+    fn current_move(&mut self, depth: u8, mv: Move, number: usize) {
+        let _ = writeln!(
+            self.out,
+            "{{\"event\":\"currmove\",\"depth\":{},\"move\":\"{}\",\"number\":{}}}",
+            depth,
+            chess960::to_uci(&self.board, mv, self.chess960),
+            number
+        );
+    }
+
+    fn pv(&mut self, info: &SearchInfo) {
+        self.info("pv", info);
+    }
+
+    fn iteration(&mut self, info: &SearchInfo) {
+        self.info("iteration", info);
+    }
+
+    fn result(&mut self, result: &SearchResult) {
+        let optional = |mv: Option<Move>| match mv {
+            Some(mv) => format!("\"{}\"", chess960::to_uci(&self.board, mv, self.chess960)),
+            None => String::from("null"),
+        };
+        let best = optional(result.best_move);
+        // The ponder move is played after the best move
+        let ponder = match uci_moves(&self.board, &result.pv, self.chess960).get(1) {
+            Some(mv) => format!("\"{}\"", mv),
+            None => String::from("null"),
+        };
+        let _ = writeln!(
+            self.out,
+            "{{\"event\":\"result\",\"bestmove\":{},\"ponder\":{},\"score\":{},\"depth\":{},\
+             \"nodes\":{},\"time\":{},\"pv\":{}}}",
+            best,
+            ponder,
+            result.eval,
+            result.depth,
+            result.nodes,
+            result.time_millis,
+            self.pv_json(&result.pv)
+        );
+        let _ = self.out.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{position::Position, search::search};
+
+    #[test]
+    fn test_reporters() {
+        let mut pos = Position::from_fen("6k1/5ppp/8/8/8/8/8/K3R3 w - - 0 1");
+        let board = pos.board.clone();
+        let mut out = Vec::new();
+        search(
+            &mut pos,
+            3,
+            u128::MAX,
+            u64::MAX,
+            &mut UciReporter::new(&board, false, &mut out),
+        );
+        let uci = String::from_utf8(out).unwrap();
+        assert!(uci.contains("info depth 3 score mate 1 "));
+        assert!(uci.ends_with("bestmove e1e8\n"));
+
+        let mut out = Vec::new();
+        let result = search(
+            &mut pos,
+            3,
+            u128::MAX,
+            u64::MAX,
+            &mut JsonReporter::new(&board, false, &mut out),
+        );
+        let json = String::from_utf8(out).unwrap();
+        let last = json.lines().last().unwrap();
+        assert!(last.starts_with("{\"event\":\"result\",\"bestmove\":\"e1e8\",\"ponder\":null,"));
+        assert_eq!(result.pv, ["e1e8".parse().unwrap()]);
+        assert!(json.lines().all(|line| line.starts_with("{\"event\":")));
+    }
+}
@@ -0,0 +1,485 @@
+//! CPU trainer for the `(768->HIDDEN_SIZE)x2->1` SCReLU network
+//!
+//! Reads data written by `datagen`, trains with AdamW on a blend of the game
+//! result and the search score, and quantises the result with `QA`/`QB` into the
+//! raw layout of `resources/net.bin`.
+
+use std::{fs, io, thread, time::Instant};
+
+use cozy_chess::{Board, Color};
+
+use crate::{
+    datagen::{to_chess_board, ENTRY_SIZE},
+    nnue::{Architecture, Network, HIDDEN_SIZE, INPUT_SIZE, QA, QB, SCALE},
+    rng::Rng,
+};
+
+const H: usize = HIDDEN_SIZE;
+/// Centipawns for which the win probability is `sigmoid(1)`
+const EVAL_SCALE: f32 = 400.0;
+/// Weights are clipped so they stay in range once quantised
+const WEIGHT_CLIP: f32 = 1.98;
+
+// Offsets of the parameters in the flattened network
+const W1: usize = 0;
+const B1: usize = W1 + INPUT_SIZE * H;
+const W2: usize = B1 + H;
+const B2: usize = W2 + 2 * H;
+const PARAMS: usize = B2 + 1;
+
+#[derive(Clone, Debug)]
+pub struct TrainerOptions {
+    pub data: String,
+    pub out: String,
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub threads: usize,
+    /// How much of the target is the game result rather than the search score
+    pub wdl: f32,
+    pub lr: f32,
+    /// The learning rate is multiplied by `lr_gamma` every `lr_step` epochs
+    pub lr_step: usize,
+    pub lr_gamma: f32,
+    pub weight_decay: f32,
+}
+
+impl Default for TrainerOptions {
+    fn default() -> Self {
+        Self {
+            data: String::from("data.bin"),
+            out: String::from("net.bin"),
+            epochs: 10,
+            batch_size: 16384,
+            threads: 1,
+            wdl: 0.3,
+            lr: 0.001,
+            lr_step: 8,
+            lr_gamma: 0.1,
+            weight_decay: 0.01,
+        }
+    }
+}
+
+/// A training position: the active features of both perspectives
+struct Sample {
+    stm: Vec<usize>,
+    nstm: Vec<usize>,
+    score: f32,
+    /// From the side to move's point of view, 0 to 1
+    result: f32,
+}
+
+/// AdamW state of every parameter
+struct Optimizer {
+    momentum: Vec<f32>,
+    velocity: Vec<f32>,
+    step: i32,
+}
+
+const BETA1: f32 = 0.9;
+const BETA2: f32 = 0.999;
+const EPSILON: f32 = 1e-8;
+
+impl Optimizer {
+    fn new() -> Self {
+        Self {
+            momentum: vec![0.0; PARAMS],
+            velocity: vec![0.0; PARAMS],
+            step: 0,
+        }
+    }
+
+    fn update(&mut self, params: &mut [f32], grads: &[f32], lr: f32, weight_decay: f32) {
+        self.step += 1;
+        let correction1 = 1.0 - BETA1.powi(self.step);
+        let correction2 = 1.0 - BETA2.powi(self.step);
+
+        for i in 0..PARAMS {
+            let m = &mut self.momentum[i];
+            let v = &mut self.velocity[i];
+            *m = BETA1 * *m + (1.0 - BETA1) * grads[i];
+            *v = BETA2 * *v + (1.0 - BETA2) * grads[i] * grads[i];
+
+            let p = &mut params[i];
+            *p -= lr * weight_decay * *p;
+            *p -= lr * (*m / correction1) / ((*v / correction2).sqrt() + EPSILON);
+            *p = p.clamp(-WEIGHT_CLIP, WEIGHT_CLIP);
+        }
+    }
+}
+
+pub fn train(options: &TrainerOptions) -> io::Result<()> {
+    let data = load_data(&options.data)?;
+    if data.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no positions to train on",
+        ));
+    }
+    println!("Loaded {} positions from {}", data.len(), options.data);
+
+    let mut rng = Rng::new(0x7353_4e4e);
+    let mut params = init_params(&mut rng);
+    let mut optimizer = Optimizer::new();
+    let mut order: Vec<usize> = (0..data.len()).collect();
+    let mut lr = options.lr;
+    let threads = options.threads.max(1);
+
+    for epoch in 1..=options.epochs {
+        let start_time = Instant::now();
+
+        // Shuffle the positions
+        for i in (1..order.len()).rev() {
+            order.swap(i, rng.below(i as u64 + 1) as usize);
+        }
+
+        let mut epoch_loss = 0.0;
+        for batch in order.chunks(options.batch_size) {
+            let chunk_size = batch.len().div_ceil(threads);
+            let (mut grads, loss) = thread::scope(|s| {
+                let handles: Vec<_> = batch
+                    .chunks(chunk_size)
+                    .map(|chunk| {
+                        let (data, params) = (&data, &params);
+                        s.spawn(move || {
+                            let mut grads = vec![0.0; PARAMS];
+                            let mut loss = 0.0;
+                            for &i in chunk {
+                                loss += backprop(params, &data[i], options.wdl, &mut grads);
+                            }
+                            (grads, loss)
+                        })
+                    })
+                    .collect();
+
+                let mut results = handles.into_iter().map(|h| h.join().unwrap());
+                let (mut grads, mut loss) = results.next().unwrap();
+                for (thread_grads, thread_loss) in results {
+                    for (g, t) in grads.iter_mut().zip(&thread_grads) {
+                        *g += t;
+                    }
+                    loss += thread_loss;
+                }
+                (grads, loss)
+            });
+
+            // Average over the batch
+            let scale = 1.0 / batch.len() as f32;
+            grads.iter_mut().for_each(|g| *g *= scale);
+            optimizer.update(&mut params, &grads, lr, options.weight_decay);
+            epoch_loss += loss;
+        }
+
+        println!(
+            "epoch {} loss {:.6} lr {} time {:.1}s",
+            epoch,
+            epoch_loss / data.len() as f32,
+            lr,
+            start_time.elapsed().as_secs_f32()
+        );
+
+        // Save after every epoch so an interrupted run isn't lost
+        fs::write(&options.out, quantise(&params))?;
+
+        if options.lr_step > 0 && epoch % options.lr_step == 0 {
+            lr *= options.lr_gamma;
+        }
+    }
+
+    println!("Wrote network to {}", options.out);
+    Ok(())
+}
+
+/// Reads the binary datagen format, or the text format for `.txt` files
+fn load_data(path: &str) -> io::Result<Vec<Sample>> {
+    let bytes = fs::read(path)?;
+
+    if path.ends_with(".txt") {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid line: {}", line),
+            )
+        };
+        String::from_utf8_lossy(&bytes)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let mut parts = line.split('|').map(str::trim);
+                let (Some(fen), Some(score), Some(result)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    return Err(invalid(line));
+                };
+                let board = Board::from_fen(fen, false).map_err(|_| invalid(line))?;
+                let score: i32 = score.parse().map_err(|_| invalid(line))?;
+                let result: f32 = result.parse().map_err(|_| invalid(line))?;
+
+                // The text format is from white's point of view
+                let (score, result) = match board.side_to_move() {
+                    Color::White => (score, result),
+                    Color::Black => (-score, 1.0 - result),
+                };
+                let score = score.clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+                Ok(decode(&to_chess_board(&board, score, result)))
+            })
+            .collect()
+    } else {
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("data size is not a multiple of {} bytes", ENTRY_SIZE),
+            ));
+        }
+        Ok(bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| decode(entry.try_into().unwrap()))
+            .collect())
+    }
+}
+
+/// Decodes a bulletformat `ChessBoard` into the features of both perspectives
+fn decode(entry: &[u8; ENTRY_SIZE]) -> Sample {
+    let mut occ = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+    let mut sample = Sample {
+        stm: Vec::with_capacity(32),
+        nstm: Vec::with_capacity(32),
+        score: f32::from(i16::from_le_bytes([entry[24], entry[25]])),
+        result: f32::from(entry[26]) / 2.0,
+    };
+
+    let mut i = 0;
+    while occ != 0 {
+        let sq = occ.trailing_zeros() as usize;
+        let nibble = usize::from(entry[8 + i / 2] >> (4 * (i & 1))) & 0xF;
+        let (colour, pc) = (nibble >> 3, nibble & 7);
+
+        sample.stm.push(384 * colour + 64 * pc + sq);
+        sample.nstm.push(384 * (colour ^ 1) + 64 * pc + (sq ^ 56));
+
+        i += 1;
+        occ &= occ - 1;
+    }
+    sample
+}
+
+fn init_params(rng: &mut Rng) -> Vec<f32> {
+    let mut uniform = |limit: f32| (rng.below(1 << 24) as f32 / (1 << 23) as f32 - 1.0) * limit;
+
+    let mut params = vec![0.0; PARAMS];
+    for p in &mut params[W1..B1] {
+        *p = uniform(1.0 / (32.0f32).sqrt());
+    }
+    for p in &mut params[W2..B2] {
+        *p = uniform(1.0 / (2.0 * H as f32).sqrt());
+    }
+    params
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// The layers of the float network for one position
+struct Forward {
+    /// Hidden layer of the side to move and of the other side
+    accs: [Vec<f32>; 2],
+    activated: [Vec<f32>; 2],
+    /// Before scaling to centipawns
+    out: f32,
+}
+
+fn forward(params: &[f32], sample: &Sample) -> Forward {
+    let bias = &params[B1..W2];
+    let mut accs = [bias.to_vec(), bias.to_vec()];
+    for (acc, features) in accs.iter_mut().zip([&sample.stm, &sample.nstm]) {
+        for &f in features {
+            let weights = &params[W1 + f * H..W1 + (f + 1) * H];
+            acc.iter_mut().zip(weights).for_each(|(a, w)| *a += w);
+        }
+    }
+
+    // SCReLU activated hidden layer and the output
+    let activated = accs.each_ref().map(|acc| {
+        acc.iter()
+            .map(|x| x.clamp(0.0, 1.0).powi(2))
+            .collect::<Vec<_>>()
+    });
+    let out = params[B2]
+        + activated
+            .iter()
+            .flatten()
+            .zip(&params[W2..B2])
+            .map(|(a, w)| a * w)
+            .sum::<f32>();
+
+    Forward {
+        accs,
+        activated,
+        out,
+    }
+}
+
+/// Adds the gradients of the loss of one position, returning the loss
+fn backprop(params: &[f32], sample: &Sample, wdl: f32, grads: &mut [f32]) -> f32 {
+    let Forward {
+        accs,
+        activated,
+        out,
+    } = forward(params, sample);
+    let out_weights = &params[W2..B2];
+
+    let output_scale = SCALE as f32 / EVAL_SCALE;
+    let prediction = sigmoid(out * output_scale);
+    let target = wdl * sample.result + (1.0 - wdl) * sigmoid(sample.score / EVAL_SCALE);
+    let error = prediction - target;
+
+    // d loss / d out
+    let grad = 2.0 * error * prediction * (1.0 - prediction) * output_scale;
+    grads[B2] += grad;
+
+    for (side, (acc, features)) in accs.iter().zip([&sample.stm, &sample.nstm]).enumerate() {
+        let mut acc_grads = vec![0.0; H];
+        for j in 0..H {
+            grads[W2 + side * H + j] += grad * activated[side][j];
+            let x = acc[j];
+            if x > 0.0 && x < 1.0 {
+                acc_grads[j] = grad * out_weights[side * H + j] * 2.0 * x;
+            }
+        }
+
+        for (b, g) in grads[B1..W2].iter_mut().zip(&acc_grads) {
+            *b += g;
+        }
+        for &f in features {
+            let weight_grads = &mut grads[W1 + f * H..W1 + (f + 1) * H];
+            weight_grads
+                .iter_mut()
+                .zip(&acc_grads)
+                .for_each(|(w, g)| *w += g);
+        }
+    }
+
+    error * error
+}
+
+/// Quantises the network into the raw layout of `net.bin`
+fn quantise(params: &[f32]) -> Vec<u8> {
+    let quantise = |x: f32, q: i32| (x * q as f32).round().clamp(-32768.0, 32767.0) as i16;
+
+    let quantised: Vec<i16> = params[W1..W2]
+        .iter()
+        .map(|&x| quantise(x, QA))
+        .chain(params[W2..B2].iter().map(|&x| quantise(x, QB)))
+        .chain([quantise(params[B2], QA * QB)])
+        .collect();
+
+    let net = Network::from_parameters(Architecture::default(), &quantised)
+        .expect("the trainer uses the default architecture");
+    net.to_raw_bytes()
+        .expect("the default architecture has a raw layout")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bench::FENS, datagen::to_chess_board};
+
+    fn sample(fen: &str, score: i16, result: f32) -> Sample {
+        let board = Board::from_fen(fen, false).unwrap();
+        decode(&to_chess_board(&board, score, result))
+    }
+
+    fn sorted(features: &[usize]) -> Vec<usize> {
+        let mut features = features.to_vec();
+        features.sort_unstable();
+        features
+    }
+
+    #[test]
+    fn test_decode() {
+        // Black to move, so the side to move's board is flipped
+        let sample = sample("4k3/8/8/8/8/8/8/4K2R b - - 0 1", -35, 1.0);
+        // Own king on e1, their king on e8 and rook on h8
+        assert_eq!(
+            sorted(&sample.stm),
+            [320 + 4, 384 + 192 + 63, 384 + 320 + 60]
+        );
+        // Their king on e1 and rook on h1, own king on e8
+        assert_eq!(sorted(&sample.nstm), [192 + 7, 320 + 4, 384 + 320 + 60]);
+        assert_eq!(sample.score, -35.0);
+        assert_eq!(sample.result, 1.0);
+    }
+
+    #[test]
+    fn test_gradients() {
+        let sample = sample("4k3/8/8/8/8/8/8/4K2R w - - 0 1", 300, 1.0);
+
+        // A few hidden neurons, with every input away from the kinks of SCReLU
+        let mut rng = Rng::new(1);
+        let mut params = vec![0.0; PARAMS];
+        for j in 0..4 {
+            params[B1 + j] = 0.1;
+            for &f in sample.stm.iter().chain(&sample.nstm) {
+                params[W1 + f * H + j] = 0.05 + rng.below(100) as f32 / 2000.0;
+            }
+            params[W2 + j] = 0.5 - 0.2 * j as f32;
+            params[W2 + H + j] = -0.3 + 0.1 * j as f32;
+        }
+        params[B2] = 0.1;
+
+        let mut grads = vec![0.0; PARAMS];
+        backprop(&params, &sample, 0.5, &mut grads);
+
+        let checked = [
+            W1 + sample.stm[0] * H,
+            W1 + sample.nstm[1] * H + 2,
+            B1 + 1,
+            W2 + 3,
+            W2 + H,
+            B2,
+        ];
+        let eps = 1e-3;
+        for i in checked {
+            let loss = |delta: f32| {
+                let mut params = params.clone();
+                params[i] += delta;
+                backprop(&params, &sample, 0.5, &mut vec![0.0; PARAMS])
+            };
+            let numeric = (loss(eps) - loss(-eps)) / (2.0 * eps);
+            assert!(grads[i] != 0.0, "parameter {}", i);
+            assert!(
+                (grads[i] - numeric).abs() <= 0.01 * grads[i].abs(),
+                "parameter {}: {} vs {}",
+                i,
+                grads[i],
+                numeric
+            );
+        }
+    }
+
+    #[test]
+    fn test_quantise() {
+        // On the grid of the quantised weights, so only the integer arithmetic
+        // of the quantised network differs
+        let mut params = init_params(&mut Rng::new(2));
+        let snap = |x: &mut f32, q: i32| *x = (*x * q as f32).round() / q as f32;
+        params[W1..W2].iter_mut().for_each(|x| snap(x, QA));
+        params[W2..B2].iter_mut().for_each(|x| snap(x, QB));
+        snap(&mut params[B2], QA * QB);
+
+        let net = Network::from_bytes(&quantise(&params)).unwrap();
+        for fen in &FENS[..10] {
+            let sample = sample(fen, 0, 0.5);
+            let float = forward(&params, &sample).out * SCALE as f32;
+            let quantised = net.evaluate_features(&sample.stm, &sample.nstm);
+            assert!(
+                (float - quantised as f32).abs() <= 2.0,
+                "{}: {} vs {}",
+                fen,
+                float,
+                quantised
+            );
+        }
+    }
+}
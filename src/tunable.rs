@@ -0,0 +1,167 @@
+//! Search parameters tunable with SPSA
+//!
+//! Every parameter is declared once in `tunables!` with its default, range and
+//! SPSA step. Normal builds compile each one to a constant. Builds with the
+//! `tune` feature expose them as UCI spin options instead, so an external
+//! match runner can change them between games.
+
+#[cfg(feature = "tune")]
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// A search parameter and the range SPSA may move it in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tunable {
+    pub name: &'static str,
+    pub default: i32,
+    pub min: i32,
+    pub max: i32,
+    pub step: i32,
+}
+
+/// Declares `name: default, min, max, step;` parameters, each read with a
+/// function of the same name
+macro_rules! tunables {
+    ($($(#[doc = $doc:literal])* $name:ident: $default:expr, $min:expr, $max:expr, $step:expr;)*) => {
+        #[cfg(feature = "tune")]
+        #[allow(non_upper_case_globals)]
+        mod values {
+            use std::sync::atomic::AtomicI32;
+
+            $(pub static $name: AtomicI32 = AtomicI32::new($default);)*
+        }
+
+        $(
+            $(#[doc = $doc])*
+            #[cfg(feature = "tune")]
+            pub fn $name() -> i32 {
+                values::$name.load(Ordering::Relaxed)
+            }
+
+            $(#[doc = $doc])*
+            #[cfg(not(feature = "tune"))]
+            pub const fn $name() -> i32 {
+                $default
+            }
+        )*
+
+        pub const TUNABLES: &[Tunable] = &[$(Tunable {
+            name: stringify!($name),
+            default: $default,
+            min: $min,
+            max: $max,
+            step: $step,
+        }),*];
+
+        #[cfg(feature = "tune")]
+        fn atomic(name: &str) -> Option<&'static AtomicI32> {
+            match name {
+                $(stringify!($name) => Some(&values::$name),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+tunables! {
+    /// Reverse futility pruning is only done up to this depth
+    rfp_depth: 6, 2, 12, 1;
+    /// Margin per ply of depth for reverse futility pruning
+    rfp_margin: 80, 20, 200, 10;
+    /// Largest weight of a correction history update, out of 256
+    corr_hist_max_weight: 16, 4, 64, 3;
+}
+
+impl Tunable {
+    /// The value the search uses, changed by `set` in builds with the `tune`
+    /// feature
+    pub fn value(&self) -> i32 {
+        #[cfg(feature = "tune")]
+        return atomic(self.name).unwrap().load(Ordering::Relaxed);
+        #[cfg(not(feature = "tune"))]
+        self.default
+    }
+}
+
+/// Sets a parameter from a UCI option, returns `false` for unknown names
+#[cfg(feature = "tune")]
+pub fn set(name: &str, value: i32) -> bool {
+    let Some(tunable) = TUNABLES.iter().find(|t| t.name == name) else {
+        return false;
+    };
+    atomic(name)
+        .unwrap()
+        .store(value.clamp(tunable.min, tunable.max), Ordering::Relaxed);
+    true
+}
+
+/// Runs `f` with every parameter at its default, then restores the values
+#[cfg(feature = "tune")]
+pub fn with_defaults<T>(f: impl FnOnce() -> T) -> T {
+    let saved: Vec<i32> = TUNABLES
+        .iter()
+        .map(|t| atomic(t.name).unwrap().swap(t.default, Ordering::Relaxed))
+        .collect();
+    let result = f();
+    for (t, value) in TUNABLES.iter().zip(saved) {
+        atomic(t.name).unwrap().store(value, Ordering::Relaxed);
+    }
+    result
+}
+
+/// Runs `f`, parameters are always at their defaults without the `tune` feature
+#[cfg(not(feature = "tune"))]
+pub fn with_defaults<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+/// The current parameters in the `config.json` format of Weather Factory, so a
+/// tuning run can start from values set with `setoption`
+pub fn weather_factory_json() -> String {
+    let entries: Vec<String> = TUNABLES
+        .iter()
+        .map(|t| {
+            format!(
+                "    \"{}\": {{\n        \"value\": {},\n        \"min_value\": {},\n        \"max_value\": {},\n        \"step\": {}\n    }}",
+                t.name,
+                t.value(),
+                t.min,
+                t.max,
+                t.step
+            )
+        })
+        .collect();
+    format!("{{\n{}\n}}", entries.join(",\n"))
+}
+
+/// The current parameters in the SPSA input format of OpenBench:
+/// `name, int, value, min, max, step, learning rate`
+pub fn openbench_input() -> String {
+    TUNABLES
+        .iter()
+        .map(|t| {
+            format!(
+                "{}, int, {}, {}, {}, {}, 0.002",
+                t.name,
+                t.value(),
+                t.min,
+                t.max,
+                t.step
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tunable_ranges() {
+        for t in TUNABLES {
+            assert!(t.min <= t.default && t.default <= t.max, "{}", t.name);
+            assert!(t.step > 0 && t.step <= t.max - t.min, "{}", t.name);
+        }
+        assert_eq!(rfp_margin(), TUNABLES[1].default);
+    }
+}
@@ -0,0 +1,416 @@
+//! Texel tuning of the evaluation
+//!
+//! Both the hand-crafted evaluation and the material scaling of the network
+//! output are linear in their parameters, so every position is reduced once to
+//! the coefficient of each parameter. The parameters are then fitted with Adam
+//! on the squared error between `sigmoid(k * eval / 400)` and the game result,
+//! after picking the `k` that best fits the current parameters.
+//!
+//! Positions are read from EPD files with a result (`c9 "1-0";`, `[0.5]`, ...),
+//! or from the text and binary output of `datagen`.
+
+use std::{fmt::Write, fs, io, time::Instant};
+
+use cozy_chess::{Board, Color, Piece, Rank, Square};
+
+use crate::{
+    datagen::{from_chess_board, ENTRY_SIZE},
+    hce::{self, Features, Param, ATTACKERS, MAX_PHASE, PARAM_COUNT, S},
+    nnue,
+    position::{Position, SCALE_BASE, SEE_VALS},
+};
+
+/// The parameters being tuned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// Every parameter of the hand-crafted evaluation
+    Classical,
+    /// `SCALE_BASE` and `SEE_VALS` as used by `Position::scale`
+    Scale,
+}
+
+#[derive(Clone, Debug)]
+pub struct TunerOptions {
+    pub data: String,
+    pub target: Target,
+    pub epochs: usize,
+    pub lr: f64,
+}
+
+impl Default for TunerOptions {
+    fn default() -> Self {
+        Self {
+            data: String::from("data.epd"),
+            target: Target::Classical,
+            epochs: 2000,
+            lr: 1.0,
+        }
+    }
+}
+
+/// A position reduced to the coefficients of the parameters
+struct Sample {
+    coefs: Vec<(usize, f64)>,
+    /// From white's point of view, 0 to 1
+    result: f64,
+}
+
+impl Sample {
+    fn eval(&self, params: &[f64]) -> f64 {
+        self.coefs.iter().map(|&(i, c)| c * params[i]).sum()
+    }
+}
+
+/// Collects the features of the hand-crafted evaluation, tapered by the phase
+struct Coefficients {
+    coefs: Vec<f64>,
+    phase: f64,
+}
+
+impl Features for Coefficients {
+    fn add(&mut self, color: Color, param: Param, count: i32) {
+        let count = match color {
+            Color::White => f64::from(count),
+            Color::Black => -f64::from(count),
+        };
+        // Middlegame and endgame halves are separate parameters
+        let index = 2 * param.index();
+        self.coefs[index] += count * self.phase;
+        self.coefs[index + 1] += count * (1.0 - self.phase);
+    }
+}
+
+const ADAM_BETA1: f64 = 0.9;
+const ADAM_BETA2: f64 = 0.999;
+const ADAM_EPSILON: f64 = 1e-8;
+
+pub fn tune(options: &TunerOptions) -> io::Result<()> {
+    if options.target == Target::Scale && nnue::embedded_network().arch().output_buckets > 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the network has output buckets, so its output isn't scaled",
+        ));
+    }
+
+    let positions = load_positions(&options.data)?;
+    if positions.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no positions to tune on",
+        ));
+    }
+    println!("Loaded {} positions from {}", positions.len(), options.data);
+
+    let mut params = initial_params(options.target);
+    let samples: Vec<Sample> = positions
+        .iter()
+        .map(|(board, result)| Sample {
+            coefs: coefficients(board, options.target),
+            result: *result,
+        })
+        .collect();
+
+    let k = fit_k(&samples, &params);
+    println!("k {:.4} loss {:.6}", k, loss(&samples, &params, k));
+
+    let mut momentum = vec![0.0; params.len()];
+    let mut velocity = vec![0.0; params.len()];
+    let start_time = Instant::now();
+
+    for epoch in 1..=options.epochs {
+        let mut grads = vec![0.0; params.len()];
+        for sample in &samples {
+            let prediction = sigmoid(k * sample.eval(&params) / 400.0);
+            let grad = (prediction - sample.result) * prediction * (1.0 - prediction);
+            for &(i, c) in &sample.coefs {
+                grads[i] += grad * c;
+            }
+        }
+
+        let correction1 = 1.0 - ADAM_BETA1.powi(epoch as i32);
+        let correction2 = 1.0 - ADAM_BETA2.powi(epoch as i32);
+        for i in 0..params.len() {
+            let grad = grads[i] / samples.len() as f64;
+            momentum[i] = ADAM_BETA1 * momentum[i] + (1.0 - ADAM_BETA1) * grad;
+            velocity[i] = ADAM_BETA2 * velocity[i] + (1.0 - ADAM_BETA2) * grad * grad;
+            params[i] -= options.lr * (momentum[i] / correction1)
+                / ((velocity[i] / correction2).sqrt() + ADAM_EPSILON);
+        }
+
+        if epoch % 100 == 0 || epoch == options.epochs {
+            println!(
+                "epoch {} loss {:.6} time {:.1}s",
+                epoch,
+                loss(&samples, &params, k),
+                start_time.elapsed().as_secs_f32()
+            );
+        }
+    }
+
+    println!();
+    print!("{}", format_params(options.target, &params));
+    Ok(())
+}
+
+/// Reads positions and results from white's point of view
+fn load_positions(path: &str) -> io::Result<Vec<(Board, f64)>> {
+    let bytes = fs::read(path)?;
+    let invalid = |what: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid position: {}", what),
+        )
+    };
+
+    if path.ends_with(".epd") || path.ends_with(".txt") {
+        String::from_utf8_lossy(&bytes)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| parse_line(line).ok_or_else(|| invalid(line)))
+            .collect()
+    } else {
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("data size is not a multiple of {} bytes", ENTRY_SIZE),
+            ));
+        }
+        // Binary positions are from the side to move's point of view, which
+        // always has white to move once decoded
+        bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| {
+                from_chess_board(entry.try_into().unwrap())
+                    .map(|(board, _, result)| (board, f64::from(result)))
+                    .ok_or_else(|| invalid("binary entry"))
+            })
+            .collect()
+    }
+}
+
+/// Parses `<fen> | <score> | <result>` datagen lines, or an EPD line with the
+/// result in a `c9` opcode or in brackets
+fn parse_line(line: &str) -> Option<(Board, f64)> {
+    let (board, rest) = if let Some((fen, rest)) = line.split_once('|') {
+        let result = rest.rsplit('|').next()?;
+        (Board::from_fen(fen.trim(), false).ok()?, result)
+    } else {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let fen = format!("{} 0 1", fields.get(..4)?.join(" "));
+        let rest = line.splitn(5, char::is_whitespace).nth(4).unwrap_or("");
+        (Board::from_fen(&fen, false).ok()?, rest)
+    };
+
+    let result = match rest.split_once("c9") {
+        Some((_, c9)) => c9.split(';').next()?,
+        None => rest.split_whitespace().last()?,
+    };
+    let result = result.trim_matches(|c: char| c.is_whitespace() || "\"[];".contains(c));
+    let result = match result {
+        "1-0" => 1.0,
+        "0-1" => 0.0,
+        "1/2-1/2" => 0.5,
+        result => result.parse().ok().filter(|r| (0.0..=1.0).contains(r))?,
+    };
+    Some((board, result))
+}
+
+fn initial_params(target: Target) -> Vec<f64> {
+    match target {
+        Target::Classical => Param::all()
+            .into_iter()
+            .flat_map(|param| {
+                let S(mg, eg) = param.value();
+                [f64::from(mg), f64::from(eg)]
+            })
+            .collect(),
+        Target::Scale => [SCALE_BASE]
+            .into_iter()
+            .chain(SEE_VALS)
+            .map(f64::from)
+            .collect(),
+    }
+}
+
+/// The coefficient of every parameter, from white's point of view
+fn coefficients(board: &Board, target: Target) -> Vec<(usize, f64)> {
+    let coefs = match target {
+        Target::Classical => {
+            let mut coefs = Coefficients {
+                coefs: vec![0.0; 2 * PARAM_COUNT],
+                phase: f64::from(hce::phase(board)) / f64::from(MAX_PHASE),
+            };
+            hce::features(board, &mut coefs);
+            coefs.coefs
+        }
+        Target::Scale => {
+            // eval = out * (SCALE_BASE + sum(count * SEE_VALS) / 32) / 1024
+            let pos = Position::from_fen(&board.to_string());
+            let (boys, opps) = (
+                &pos.acc[board.side_to_move() as usize],
+                &pos.acc[!board.side_to_move() as usize],
+            );
+            let out = pos
+                .network()
+                .out(boys, opps, board.occupied().len() as usize);
+            let out = f64::from(match board.side_to_move() {
+                Color::White => out,
+                Color::Black => -out,
+            });
+
+            let mut coefs = vec![out / 1024.0];
+            coefs.extend(Piece::ALL.map(|pc| {
+                let count = f64::from(board.pieces(pc).len());
+                // Only knights to queens count as material
+                match pc {
+                    Piece::Pawn | Piece::King => 0.0,
+                    _ => out * count / 32.0 / 1024.0,
+                }
+            }));
+            coefs
+        }
+    };
+
+    coefs
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, c)| c != 0.0)
+        .collect()
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+fn loss(samples: &[Sample], params: &[f64], k: f64) -> f64 {
+    samples
+        .iter()
+        .map(|sample| (sigmoid(k * sample.eval(params) / 400.0) - sample.result).powi(2))
+        .sum::<f64>()
+        / samples.len() as f64
+}
+
+/// Finds the `k` minimising the loss with a golden section search
+fn fit_k(samples: &[Sample], params: &[f64]) -> f64 {
+    let ratio = (5.0f64.sqrt() - 1.0) / 2.0;
+    let (mut low, mut high) = (0.0, 10.0);
+    while high - low > 1e-4 {
+        let a = high - ratio * (high - low);
+        let b = low + ratio * (high - low);
+        if loss(samples, params, a) < loss(samples, params, b) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// Formats the parameters as the constants they replace
+fn format_params(target: Target, params: &[f64]) -> String {
+    let round = |x: f64| x.round() as i32;
+    let mut out = String::new();
+    match target {
+        Target::Classical => {
+            let s = |param: Param| {
+                let i = 2 * param.index();
+                format!("S({}, {})", round(params[i]), round(params[i + 1]))
+            };
+            let list = |params: Vec<Param>| params.into_iter().map(s).collect::<Vec<_>>();
+
+            let consts = [
+                ("MATERIAL", list(Piece::ALL.map(Param::Material).to_vec())),
+                ("MOBILITY", list(ATTACKERS.map(Param::Mobility).to_vec())),
+                (
+                    "PASSED_PAWN",
+                    list(Rank::ALL.map(Param::PassedPawn).to_vec()),
+                ),
+                (
+                    "KING_ATTACK",
+                    list(ATTACKERS.map(Param::KingAttack).to_vec()),
+                ),
+            ];
+            for (name, values) in consts {
+                writeln!(
+                    out,
+                    "const {}: [S; {}] = [{}];",
+                    name,
+                    values.len(),
+                    values.join(", ")
+                )
+                .unwrap();
+            }
+            for (name, param) in [
+                ("BISHOP_PAIR", Param::BishopPair),
+                ("DOUBLED_PAWN", Param::DoubledPawn),
+                ("ISOLATED_PAWN", Param::IsolatedPawn),
+                ("PAWN_SHIELD", Param::PawnShield),
+            ] {
+                writeln!(out, "const {}: S = {};", name, s(param)).unwrap();
+            }
+
+            writeln!(out, "#[rustfmt::skip]").unwrap();
+            writeln!(out, "const PSQT: [[S; 64]; 6] = [").unwrap();
+            for pc in Piece::ALL {
+                writeln!(out, "    // {:?}", pc).unwrap();
+                writeln!(out, "    [").unwrap();
+                for rank in Square::ALL.chunks(8) {
+                    let row = list(rank.iter().map(|&sq| Param::Psqt(pc, sq)).collect());
+                    writeln!(out, "        {},", row.join(", ")).unwrap();
+                }
+                writeln!(out, "    ],").unwrap();
+            }
+            writeln!(out, "];").unwrap();
+        }
+        Target::Scale => {
+            writeln!(
+                out,
+                "pub const SEE_VALS: [i32; 6] = [{}];",
+                params[1..]
+                    .iter()
+                    .map(|&x| round(x).to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+            .unwrap();
+            writeln!(out, "pub const SCALE_BASE: i32 = {};", round(params[0])).unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let results = [
+            ("8/8/8/8/8/8/8/K1k5 w - - c9 \"1-0\";", 1.0),
+            ("8/8/8/8/8/8/8/K1k5 b - - 0 1 [0.5]", 0.5),
+            ("8/8/8/8/8/8/8/K1k5 w - - 0 1 | -35 | 0.0", 0.0),
+            ("8/8/8/8/8/8/8/K1k5 w - - c9 \"1/2-1/2\";", 0.5),
+        ];
+        for (line, result) in results {
+            assert_eq!(parse_line(line).map(|(_, r)| r), Some(result), "{}", line);
+        }
+        assert!(parse_line("8/8/8/8/8/8/8/K1k5 w - -").is_none());
+    }
+
+    #[test]
+    // The coefficients reproduce the evaluation they were taken from
+    fn test_classical_coefficients() {
+        let board = Board::from_fen(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            false,
+        )
+        .unwrap();
+        let params = initial_params(Target::Classical);
+        let sample = Sample {
+            coefs: coefficients(&board, Target::Classical),
+            result: 0.5,
+        };
+        let expected = hce::Trace::new(&board).score();
+        assert!((sample.eval(&params) - f64::from(expected)).abs() < 1.0);
+    }
+}
@@ -1,54 +1,113 @@
-use std::{iter::Peekable, str::SplitWhitespace};
+use std::{io::BufRead, iter::Peekable, str::SplitWhitespace};
+
+use crate::{bench::DEFAULT_BENCH_DEPTH, perft};
 
 pub trait Uci {
-    /// The main loop of the UCI protocol
+    /// The main loop of the UCI protocol, on stdin
     fn uci_loop(&mut self) {
-        loop {
-            let mut input = String::new();
-            std::io::stdin().read_line(&mut input).unwrap();
-            let mut args = input.split_whitespace().peekable();
+        self.run(std::io::stdin().lock());
+    }
 
-            match args.next() {
-                Some("uci") => {
-                    self.send("id name Tsunami");
-                    self.send("id author github.com/thomasdamcevski");
-                    self.send("uciok");
-                }
-                Some("isready") => {
-                    self.send("readyok");
-                }
-                Some("ucinewgame") => {}
-                Some("position") => {
-                    self.set_position(&mut args);
-                }
-                Some("go") => {
-                    self.go(&mut args);
-                }
-                Some("eval") => {
-                    self.display_eval();
-                }
-                Some("d") => {
-                    self.display();
-                }
-                Some("quit") => {
-                    break;
-                }
-                _ => {
-                    self.send("Unrecognized command");
+    /// Handles commands until `quit` or the end of the input
+    fn run(&mut self, input: impl BufRead) {
+        for line in input.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if !self.command(&line) {
+                break;
+            }
+        }
+    }
+
+    /// Handles a single command
+    /// Returns false once the engine should quit
+    fn command(&mut self, input: &str) -> bool {
+        let mut args = input.split_whitespace().peekable();
+
+        match args.next() {
+            Some("uci") => {
+                self.send("id name Tsunami");
+                self.send("id author github.com/thomasdamcevski");
+                self.send_options();
+                self.send("uciok");
+            }
+            Some("isready") => {
+                self.send("readyok");
+            }
+            Some("ucinewgame") => {}
+            Some("setoption") => {
+                // setoption name <id> [value <x>]
+                // Both the name and the value may contain spaces
+                let mut name = Vec::new();
+                let mut value = Vec::new();
+                let mut in_value = false;
+                for arg in args.by_ref() {
+                    match arg {
+                        "name" if !in_value && name.is_empty() => {}
+                        "value" if !in_value => in_value = true,
+                        _ if in_value => value.push(arg),
+                        _ => name.push(arg),
+                    }
                 }
+                self.set_option(&name.join(" "), &value.join(" "));
+            }
+            Some("position") => {
+                self.set_position(&mut args);
+            }
+            Some("go") => {
+                self.go(&mut args);
+            }
+            Some("eval") => {
+                self.display_eval(args.next() == Some("trace"));
+            }
+            Some("spsa") => {
+                self.display_tunables(args.next() == Some("openbench"));
+            }
+            Some("bench") => {
+                let depth = args.next().and_then(|depth| depth.parse().ok());
+                self.bench(depth.unwrap_or(DEFAULT_BENCH_DEPTH));
+            }
+            Some("perft-suite") => {
+                let (depth, table) = perft::parse_suite_options(args.by_ref());
+                perft::run_suite(depth, table, |line| self.send(line));
+            }
+            Some("d") => {
+                self.display();
+            }
+            Some("quit") => {
+                return false;
+            }
+            _ => {
+                self.send("Unrecognized command");
             }
         }
+        true
     }
 
     /// The main entry point for the search
     fn go(&mut self, args: &mut Peekable<SplitWhitespace>);
 
-    /// Display the static NNUE evaluation of the current position
-    fn display_eval(&mut self);
+    /// Display the static evaluation of the current position
+    /// With `trace`, also break the hand-crafted evaluation down by term
+    fn display_eval(&mut self, trace: bool);
 
     /// Sets the position of the board from a list of moves, or from a FEN string
     fn set_position(&mut self, args: &mut Peekable<SplitWhitespace>);
 
+    /// Sets an option sent by the GUI with `setoption`
+    fn set_option(&mut self, name: &str, value: &str);
+
+    /// Send the options the engine supports, in response to `uci`
+    fn send_options(&self) {}
+
+    /// Print the SPSA tunable parameters as Weather Factory JSON, or in the
+    /// SPSA input format of OpenBench
+    fn display_tunables(&self, openbench: bool);
+
+    /// Search the bench positions to a fixed depth and print the total nodes
+    fn bench(&mut self, depth: u8);
+
     /// Display an ASCII representation of the board
     /// As well as some other information about the position
     /// such as the FEN
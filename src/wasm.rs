@@ -0,0 +1,81 @@
+//! UCI over message passing, for the browser
+//!
+//! The host copies commands into memory from `tsunami_alloc` and runs them
+//! with `tsunami_uci`. Every line of output goes back through the imported
+//! `tsunami_output`. `web/tsunami.mjs` wraps both directions for JavaScript.
+
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    slice,
+};
+
+use crate::{uci::Uci, Tsunami};
+
+#[link(wasm_import_module = "env")]
+extern "C" {
+    /// Receives one line of output, without the newline
+    fn tsunami_output(ptr: *const u8, len: usize);
+}
+
+/// Passes complete lines to the host
+struct HostOutput(Vec<u8>);
+
+impl Write for HostOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        while let Some(end) = self.0.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.0.drain(..=end).collect();
+            // The host copies the line out before returning
+            unsafe { tsunami_output(line.as_ptr(), end) };
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+thread_local! {
+    static ENGINE: RefCell<Option<Tsunami>> = const { RefCell::new(None) };
+}
+
+/// `len` bytes for the host to write a command into
+#[no_mangle]
+pub extern "C" fn tsunami_alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// # Safety
+/// `ptr` comes from `tsunami_alloc` with the same `len`
+#[no_mangle]
+pub unsafe extern "C" fn tsunami_dealloc(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+/// Runs the UCI commands, one per line, in the `len` bytes at `ptr`
+/// Returns 0 after `quit`, the next command then starts a new engine
+///
+/// # Safety
+/// `ptr` points to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn tsunami_uci(ptr: *const u8, len: usize) -> i32 {
+    let input = String::from_utf8_lossy(slice::from_raw_parts(ptr, len));
+    ENGINE.with(|engine| {
+        let mut engine = engine.borrow_mut();
+        let tsunami = engine.get_or_insert_with(|| {
+            let mut tsunami = Tsunami::default();
+            tsunami.set_output(HostOutput(Vec::new()));
+            tsunami
+        });
+        let running = input.lines().all(|line| tsunami.command(line));
+        if !running {
+            *engine = None;
+        }
+        i32::from(running)
+    })
+}
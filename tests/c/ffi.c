@@ -0,0 +1,80 @@
+/* Exercises the C API, exits with a non-zero status on the first failure */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "tsunami.h"
+
+#define CHECK(cond)                                                                       \
+    do {                                                                                  \
+        if (!(cond)) {                                                                    \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);      \
+            exit(1);                                                                      \
+        }                                                                                 \
+    } while (0)
+
+static void on_info(int depth, int eval, uint64_t nodes, const char *pv, void *user_data) {
+    int *iterations = user_data;
+    (*iterations)++;
+    printf("info depth %d score cp %d nodes %llu pv %s\n", depth, eval,
+           (unsigned long long)nodes, pv);
+}
+
+int main(void) {
+    TsunamiEngine *engine = tsunami_new();
+    CHECK(engine != NULL);
+
+    CHECK(tsunami_perft(engine, 4) == 197281);
+
+    CHECK(tsunami_set_fen(engine, "not a fen") == -1);
+    CHECK(tsunami_last_error() != NULL);
+    CHECK(tsunami_play_moves(engine, "e2e4 e7e5 e2e4") == -1);
+    CHECK(tsunami_perft(engine, 1) == 20);
+    CHECK(tsunami_set_option(engine, "Hash", "16") == -1);
+
+    /* Kiwipete, 48 moves including both castlings */
+    CHECK(tsunami_set_fen(engine,
+                          "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1") ==
+          0);
+    CHECK(tsunami_perft(engine, 1) == 48);
+    CHECK(tsunami_play_moves(engine, "e1g1") == 0);
+    CHECK(tsunami_perft(engine, 1) == 43);
+
+    /* Scholar's mate in one */
+    CHECK(tsunami_set_fen(engine, "rnbqkbnr/pppp1ppp/8/4p3/2B1P3/8/PPPP1PPP/RNBQK1NR w KQkq - 0 1") ==
+          0);
+    CHECK(tsunami_play_moves(engine, "d1h5 b8c6 h5f3 g8f6 f3h5") == 0);
+    CHECK(tsunami_play_moves(engine, "a7a6") == 0);
+    int eval = tsunami_eval(engine);
+    CHECK(eval > -10000 && eval < 10000);
+
+    TsunamiSearchResult result;
+    int iterations = 0;
+    CHECK(tsunami_search(engine, 3, 0, 0, on_info, &iterations, &result) == 0);
+    CHECK(strcmp(result.best_move, "h5f7") == 0);
+    CHECK(result.depth == 3);
+    CHECK(result.nodes > 0);
+    CHECK(iterations == 3);
+
+    CHECK(tsunami_search(engine, 0, 2000, 0, NULL, NULL, &result) == 0);
+    CHECK(strlen(result.best_move) >= 4);
+
+    /* Options only apply to the engine they are set on */
+    TsunamiEngine *classical = tsunami_new();
+    CHECK(classical != NULL);
+    CHECK(tsunami_set_option(classical, "Evaluation", "Classical") == 0);
+    CHECK(tsunami_set_fen(engine, "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1") ==
+          0);
+    CHECK(tsunami_set_fen(classical,
+                          "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1") == 0);
+    int nnue = tsunami_eval(engine);
+    CHECK(tsunami_eval(classical) != nnue);
+    CHECK(tsunami_set_option(classical, "Evaluation", "NNUE") == 0);
+    CHECK(tsunami_eval(classical) == nnue);
+    tsunami_free(classical);
+
+    tsunami_free(engine);
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,49 @@
+use tsunami::nnue::format::fnv1a;
+
+pub const INPUTS: usize = 768;
+pub const HIDDEN: usize = 768;
+pub const QA: i32 = 181;
+pub const QB: i32 = 64;
+pub const SCALE: i32 = 400;
+
+/// Deterministic pseudo-random weights in `-64..64`
+pub fn random_weights(count: usize) -> Vec<i16> {
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    (0..count)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % 128) as i16 - 64
+        })
+        .collect()
+}
+
+/// A network file in the current format
+pub fn network_file(
+    input_buckets: usize,
+    mirrored: bool,
+    bucket_layout: [u8; 64],
+    output_buckets: usize,
+    params: &[i16],
+) -> Vec<u8> {
+    let params: Vec<u8> = params.iter().flat_map(|p| p.to_le_bytes()).collect();
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"TSNN");
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&128u16.to_le_bytes());
+    for val in [INPUTS, HIDDEN, output_buckets] {
+        bytes.extend_from_slice(&(val as u32).to_le_bytes());
+    }
+    for val in [QA, QB, SCALE] {
+        bytes.extend_from_slice(&val.to_le_bytes());
+    }
+    bytes.extend_from_slice(&fnv1a(&params).to_le_bytes());
+    bytes.extend_from_slice(&(input_buckets as u32).to_le_bytes());
+    bytes.extend_from_slice(&u32::from(mirrored).to_le_bytes());
+    bytes.resize(64, 0);
+    bytes.extend_from_slice(&bucket_layout);
+    bytes.extend_from_slice(&params);
+    bytes
+}
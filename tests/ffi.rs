@@ -0,0 +1,58 @@
+use std::{env, fs, path::PathBuf, process::Command};
+
+const HEADER: &str = "include/tsunami.h";
+
+/// Regenerates the header from `src/ffi.rs` and compares it to the committed
+/// one, so that changed signatures or struct layouts can't go unnoticed
+#[test]
+fn test_header_is_generated() {
+    let config = cbindgen::Config::from_file("cbindgen.toml").unwrap();
+    let mut generated = Vec::new();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src("src/ffi.rs")
+        .generate()
+        .unwrap()
+        .write(&mut generated);
+    let generated = String::from_utf8(generated).unwrap();
+
+    if env::var_os("UPDATE_HEADER").is_some() {
+        fs::write(HEADER, &generated).unwrap();
+    }
+    let header = fs::read_to_string(HEADER).unwrap();
+    assert!(
+        header == generated,
+        "{} is out of date, run `UPDATE_HEADER=1 cargo test --test ffi`",
+        HEADER
+    );
+}
+/// Builds the C test program against the shared library and runs it
+#[test]
+fn test_c_program() {
+    // Cargo builds the shared library next to the test executables
+    let lib_dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let compiler = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    let exe = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("ffi_test");
+
+    // A missing compiler fails the test, set CC to use another one
+    let status = Command::new(&compiler)
+        .args(["tests/c/ffi.c", "-Wall", "-Werror", "-Iinclude", "-o"])
+        .arg(&exe)
+        .arg(format!("-L{}", lib_dir.display()))
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-ltsunami")
+        .status()
+        .unwrap_or_else(|err| panic!("running {} failed: {}", compiler, err));
+    assert!(status.success(), "compiling the C test program failed");
+
+    let output = Command::new(&exe).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("info depth 3"));
+    assert!(stdout.ends_with("ok\n"));
+}
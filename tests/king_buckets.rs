@@ -0,0 +1,34 @@
+mod common;
+
+use common::{network_file, random_weights, HIDDEN, INPUTS};
+use std::sync::Arc;
+
+use tsunami::{nnue::Network, position::Position};
+
+const BUCKETS: usize = 4;
+
+#[test]
+// Refreshing accumulators when kings change bucket or side of the board has
+// to give the same evaluation as building them from scratch
+fn test_king_bucket_refreshes() {
+    // Buckets by the rank of the king: 1st, 2nd, 3rd-4th, the rest
+    let layout = std::array::from_fn(|sq| [0, 1, 2, 2, 3, 3, 3, 3][sq / 8]);
+    let params = random_weights(BUCKETS * INPUTS * HIDDEN + HIDDEN + 2 * HIDDEN + 1);
+    let net = Network::from_bytes(&network_file(BUCKETS, true, layout, 1, &params)).unwrap();
+    assert_eq!(net.arch().input_buckets, BUCKETS);
+    assert!(net.arch().mirrored);
+    let net = Arc::new(net);
+
+    let mut pos = Position::try_from_fen_with("r3k3/pp6/8/8/8/8/PP6/R3K3 w Q - 0 1", net).unwrap();
+    let moves = [
+        "e1d1", "e8f7", "d1d2", "f7e6", "d2e3", "e6d5", "e3e2", "d5e6", "e2d1", "e6f7", "d1e1",
+        "f7e8",
+    ];
+    for mv in moves {
+        pos.make_move(mv.parse().unwrap());
+
+        let mut fresh = pos.clone();
+        fresh.update_acc();
+        assert_eq!(pos.eval(), fresh.eval(), "after {}", mv);
+    }
+}
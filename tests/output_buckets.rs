@@ -0,0 +1,37 @@
+mod common;
+
+use common::{network_file, random_weights, HIDDEN, INPUTS, QA, QB, SCALE};
+use std::sync::Arc;
+
+use tsunami::{nnue::Network, position::Position};
+
+const BUCKETS: usize = 8;
+
+#[test]
+// Each material count is evaluated by its own output bucket, without the
+// material scaling used for single-bucket networks
+fn test_output_buckets() {
+    // Only the output biases matter, they tell the buckets apart
+    let mut params = random_weights(INPUTS * HIDDEN + HIDDEN);
+    params.extend(std::iter::repeat_n(0, BUCKETS * 2 * HIDDEN));
+    params.extend((0..BUCKETS as i16).map(|bucket| 1000 * (bucket + 1)));
+
+    let net = Network::from_bytes(&network_file(1, false, [0; 64], BUCKETS, &params)).unwrap();
+    assert_eq!(net.arch().output_buckets, BUCKETS);
+    let net = Arc::new(net);
+
+    let expected = |bucket: i32| 1000 * (bucket + 1) * SCALE / (QA * QB);
+    let positions = [
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            7,
+        ),
+        ("4k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1", 4),
+        ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", 0),
+        ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", 0),
+    ];
+    for (fen, bucket) in positions {
+        let pos = Position::try_from_fen_with(fen, Arc::clone(&net)).unwrap();
+        assert_eq!(pos.eval(), expected(bucket), "{}", fen);
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use tsunami::{uci::Uci, Tsunami};
+
+/// Output shared between the engine and the test
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs a scripted session and returns the output lines
+/// The script doesn't end with `quit`, so the session stops at the end of the input
+fn session(script: &str) -> Vec<String> {
+    let output = Output::default();
+    let mut tsunami = Tsunami::default();
+    tsunami.set_output(output.clone());
+    tsunami.run(script.as_bytes());
+
+    let bytes = output.0.lock().unwrap();
+    String::from_utf8_lossy(&bytes)
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn test_handshake() {
+    let output = session("uci\nisready\n");
+    assert_eq!(output.first().map(String::as_str), Some("id name Tsunami"));
+    assert!(output
+        .iter()
+        .any(|line| line == "option name UCI_Chess960 type check default false"));
+    assert_eq!(output[output.len() - 2..], ["uciok", "readyok"]);
+}
+
+#[test]
+fn test_search() {
+    let output = session("position startpos moves e2e4 e7e5\ngo movetime 50\nquit\nisready\n");
+    assert!(output.iter().any(|line| line.starts_with("info depth 1 ")));
+    assert!(output.last().unwrap().starts_with("bestmove "));
+    // Nothing is read after quit
+    assert!(!output.iter().any(|line| line == "readyok"));
+
+    let output = session("position fen 7k/6Q1/6K1/8/8/8/8/8 b - - 0 1\ngo movetime 50\n");
+    assert_eq!(output, ["bestmove 0000"]);
+
+    // Castling in the notation of the mode
+    let fen = "position fen 6k1/8/8/8/8/8/5PPP/4K2R w K - 0 1 moves e1g1\nd\n";
+    let output = session(fen);
+    assert!(output.contains(&String::from("FEN: 6k1/8/8/8/8/8/5PPP/5RK1 b - - 1 1")));
+    let output = session(&format!("setoption name UCI_Chess960 value true\n{}", fen));
+    assert!(output[0].starts_with("info string error illegal move e1g1"));
+}
+
+#[test]
+fn test_errors_keep_position() {
+    let output = session(
+        "position startpos moves e2e4\n\
+         position startpos moves e2e4 e2e4\n\
+         position fen not a fen\n\
+         go wtime soon movetime 10\n\
+         setoption name BookDepth value deep\n\
+         d\n",
+    );
+    let errors: Vec<&str> = output
+        .iter()
+        .filter(|line| line.starts_with("info string error"))
+        .map(String::as_str)
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            "info string error illegal move e2e4",
+            "info string error invalid FEN not a fen",
+            "info string error invalid value 'soon' for wtime",
+            "info string error invalid value 'deep' for BookDepth",
+        ]
+    );
+    assert!(output.iter().any(|line| line.starts_with("bestmove ")));
+    assert!(output.contains(&String::from(
+        "FEN: rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+    )));
+}
+
+#[cfg(feature = "tune")]
+#[test]
+fn test_spsa_current_values() {
+    let output = session("setoption name rfp_margin value 100\nspsa openbench\n");
+    assert!(output.contains(&String::from("rfp_margin, int, 100, 20, 200, 10, 0.002")));
+}
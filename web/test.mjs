@@ -0,0 +1,39 @@
+// Runs a UCI session on the WebAssembly build in Node
+//   node web/test.mjs [path/to/tsunami.wasm]
+
+import assert from "node:assert/strict";
+import { readFile } from "node:fs/promises";
+
+import { Tsunami } from "./tsunami.mjs";
+
+const path = process.argv[2] ?? "target/wasm32-unknown-unknown/release/tsunami.wasm";
+let lines = [];
+const engine = await Tsunami.load(await readFile(path), (line) => lines.push(line));
+
+function session(commands) {
+  lines = [];
+  const running = engine.send(commands);
+  return { running, lines };
+}
+
+assert.ok(session("uci\nisready").lines.includes("readyok"));
+
+// The embedded network is loaded and move generation matches native builds
+assert.ok(session("eval").lines.some((line) => line.startsWith("Eval: ")));
+assert.ok(session("position startpos\ngo perft 4").lines.includes("Nodes: 197281"));
+
+// Searches are timed with the clock of the host
+const start = Date.now();
+const search = session("position startpos moves e2e4\ngo movetime 500").lines;
+assert.ok(Date.now() - start < 5000);
+assert.ok(search.some((line) => line.startsWith("info depth")));
+assert.match(search.at(-1), /^bestmove [a-h][1-8][a-h][1-8]/);
+
+// Invalid input is reported and the session goes on
+assert.match(session("position fen nonsense").lines[0], /^info string error/);
+
+// Bench node counts are the same as on native builds
+assert.match(session("bench 3").lines[0], /^280523 nodes /);
+
+assert.equal(session("quit").running, false);
+console.log("ok");
@@ -0,0 +1,57 @@
+// Tsunami compiled to WebAssembly, talking UCI
+//
+// Build the module with:
+//   cargo build --release --lib --target wasm32-unknown-unknown
+// and load target/wasm32-unknown-unknown/release/tsunami.wasm:
+//
+//   const engine = await Tsunami.load(bytes, (line) => console.log(line));
+//   engine.send("position startpos moves e2e4");
+//   engine.send("go movetime 1000");
+//
+// Commands run synchronously, searches block until they reach their time
+// limit, so run the engine in a worker (see worker.mjs) to keep pages responsive.
+
+export class Tsunami {
+  /**
+   * @param {BufferSource | Response | Promise<Response>} source the wasm module
+   * @param {(line: string) => void} onLine called with every line of output
+   */
+  static async load(source, onLine) {
+    const decoder = new TextDecoder();
+    let memory = null;
+    const imports = {
+      env: {
+        tsunami_now_ms: () => Date.now(),
+        tsunami_output: (ptr, len) => {
+          // A copy, as text can't be decoded from shared memory
+          onLine(decoder.decode(new Uint8Array(memory.buffer, ptr, len).slice()));
+        },
+      },
+    };
+    const { instance } =
+      source instanceof Response || source instanceof Promise
+        ? await WebAssembly.instantiateStreaming(source, imports)
+        : await WebAssembly.instantiate(source, imports);
+    memory = instance.exports.memory;
+    return new Tsunami(instance.exports);
+  }
+
+  constructor(exports) {
+    this.exports = exports;
+    this.encoder = new TextEncoder();
+  }
+
+  /**
+   * Runs UCI commands, one per line
+   * @returns {boolean} false after `quit`
+   */
+  send(commands) {
+    const bytes = this.encoder.encode(commands);
+    const len = Math.max(bytes.length, 1);
+    const ptr = this.exports.tsunami_alloc(len);
+    new Uint8Array(this.exports.memory.buffer, ptr, bytes.length).set(bytes);
+    const running = this.exports.tsunami_uci(ptr, bytes.length) !== 0;
+    this.exports.tsunami_dealloc(ptr, len);
+    return running;
+  }
+}
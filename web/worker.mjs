@@ -0,0 +1,17 @@
+// UCI over postMessage: every message is a command, every line of output is
+// posted back
+//
+//   const worker = new Worker("worker.mjs", { type: "module" });
+//   worker.onmessage = (event) => console.log(event.data);
+//   worker.postMessage("uci");
+//
+// Messages are handled one at a time and a search can't be interrupted, a
+// "stop" would only be read once it has ended. Every "go" needs a limit such as
+// movetime, depth or nodes.
+
+import { Tsunami } from "./tsunami.mjs";
+
+const engine = await Tsunami.load(fetch(new URL("tsunami.wasm", import.meta.url)), (line) =>
+  postMessage(line),
+);
+onmessage = (event) => engine.send(event.data);